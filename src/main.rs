@@ -23,15 +23,24 @@ fn main() {
 
     match config.subcommand {
         Some(Command::MassRename { ref config }) => {
-            if let Err(reason) = crate::ocd::mrn::run(config) {
-                eprintln!("{}", reason);
-                process::exit(1)
+            if let Err(error) = crate::ocd::mrn::run(config) {
+                eprintln!("{}", error);
+                process::exit(error.exit_code())
             }
         }
+        Some(Command::PrintConfig { ref settings }) => {
+            crate::ocd::output::print_config(settings);
+        }
         Some(Command::TimeStampSort { ref config }) => {
-            if let Err(reason) = crate::ocd::tss::run(config) {
-                eprintln!("{}", reason);
-                process::exit(1)
+            if let Err(error) = crate::ocd::tss::run(config) {
+                eprintln!("{}", error);
+                process::exit(error.exit_code())
+            }
+        }
+        Some(Command::Undo { ref config }) => {
+            if let Err(error) = crate::ocd::undo::run(config) {
+                eprintln!("{}", error);
+                process::exit(error.exit_code())
             }
         }
         None => unreachable!(),
@@ -1,6 +1,10 @@
+use crate::ocd::mrn::keywords;
 use crate::ocd::mrn::MassRenameConfig;
+use crate::ocd::settings::Settings;
 use crate::ocd::tss::TimeStampSortConfig;
+use crate::ocd::undo::UndoConfig;
 use crate::ocd::Command;
+use std::fmt;
 use std::path::{Path, PathBuf};
 
 #[remain::sorted]
@@ -24,6 +28,53 @@ impl Verbosity {
     pub fn is_silent(self) -> bool {
         matches!(self, Verbosity::Silent)
     }
+
+    pub fn is_high(self) -> bool {
+        matches!(self, Verbosity::High | Verbosity::Debug)
+    }
+}
+
+/// The names used for the settings in the configuration files.
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mode::All => write!(f, "a"),
+            Mode::Directories => write!(f, "d"),
+            Mode::Files => write!(f, "f"),
+        }
+    }
+}
+
+impl fmt::Display for Verbosity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Verbosity::Silent => write!(f, "silent"),
+            Verbosity::Low => write!(f, "low"),
+            Verbosity::Medium => write!(f, "medium"),
+            Verbosity::High => write!(f, "high"),
+            Verbosity::Debug => write!(f, "debug"),
+        }
+    }
+}
+
+/// The command line definition in `config.yaml`, with the `{keywords}`
+/// placeholder in the help of `mrn` replaced by the list of rules.
+fn definition() -> String {
+    let mut definition = String::new();
+    for line in include_str!("config.yaml").lines() {
+        if line.trim() == "{keywords}" {
+            let indent = &line[..line.len() - line.trim_start().len()];
+            for help in keywords::help().lines() {
+                definition.push_str(indent);
+                definition.push_str(help);
+                definition.push('\n');
+            }
+        } else {
+            definition.push_str(line);
+            definition.push('\n');
+        }
+    }
+    definition
 }
 
 #[derive(Debug)]
@@ -45,22 +96,48 @@ impl Config {
     }
 
     pub fn with_args(&self) -> Result<Config, String> {
-        let yaml = load_yaml!("config.yaml");
-        let app = clap::App::from_yaml(yaml);
+        let yaml = clap::YamlLoader::load_from_str(&definition()).expect("config.yaml is valid");
+        let app = clap::App::from_yaml(&yaml[0]);
         let ocd_matches = app.get_matches();
 
         match ocd_matches.subcommand() {
             ("mrn", Some(subcommand_matches)) => {
-                let subcommand_config = MassRenameConfig::new().with_args(subcommand_matches);
-                let subcommand = Some(Command::MassRename {
-                    config: subcommand_config,
-                });
+                let defaults = MassRenameConfig::new();
+                let settings =
+                    Settings::load(subcommand_matches, "mrn", &names(defaults.settings()))?;
+                let subcommand_config = defaults.with_args(&settings)?;
+                let subcommand = if subcommand_matches.is_present("print-config") {
+                    Some(Command::PrintConfig {
+                        settings: settings.describe(subcommand_config.settings()),
+                    })
+                } else {
+                    Some(Command::MassRename {
+                        config: subcommand_config,
+                    })
+                };
                 let config = Config { subcommand };
                 Ok(config)
             }
             ("tss", Some(subcommand_matches)) => {
-                let subcommand_config = TimeStampSortConfig::new().with_args(subcommand_matches);
-                let subcommand = Some(Command::TimeStampSort {
+                let defaults = TimeStampSortConfig::new();
+                let settings =
+                    Settings::load(subcommand_matches, "tss", &names(defaults.settings()))?;
+                let subcommand_config = defaults.with_args(&settings)?;
+                let subcommand = if subcommand_matches.is_present("print-config") {
+                    Some(Command::PrintConfig {
+                        settings: settings.describe(subcommand_config.settings()),
+                    })
+                } else {
+                    Some(Command::TimeStampSort {
+                        config: subcommand_config,
+                    })
+                };
+                let config = Config { subcommand };
+                Ok(config)
+            }
+            ("undo", Some(subcommand_matches)) => {
+                let subcommand_config = UndoConfig::new().with_args(subcommand_matches);
+                let subcommand = Some(Command::Undo {
                     config: subcommand_config,
                 });
                 let config = Config { subcommand };
@@ -72,6 +149,11 @@ impl Config {
     }
 }
 
+/// The names of the settings that the configuration files can set.
+fn names(settings: Vec<(&'static str, String)>) -> Vec<&'static str> {
+    settings.into_iter().map(|(name, _)| name).collect()
+}
+
 pub fn verbosity_value(matches: &clap::ArgMatches) -> Verbosity {
     let level = matches.occurrences_of("verbosity");
     let silent = matches.is_present("silent");
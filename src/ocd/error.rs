@@ -0,0 +1,145 @@
+use crate::ocd::mrn::diagnostic::Diagnostic;
+use crate::ocd::mrn::lexer::TokenizerError;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Everything that can make a subcommand fail.
+#[derive(Debug)]
+pub enum OcdError {
+    /// The mass rename rules could not be split into tokens.
+    Lex(TokenizerError),
+    /// The rules, the layout or the glob pattern given on the command line
+    /// are not valid. Errors in the rules point at where they are.
+    Parse {
+        message: String,
+        diagnostic: Option<Diagnostic>,
+    },
+    /// A rule could not be applied to a file. For `tss` the rule is the
+    /// layout.
+    Rule {
+        rule: String,
+        path: PathBuf,
+        message: String,
+    },
+    /// Reading or changing the filesystem failed.
+    Filesystem { path: PathBuf, source: io::Error },
+    /// Moving `src` to `dst` would overwrite another file: the one that
+    /// `other` would also be moved to `dst`, or the one already at `dst` if
+    /// `other` is `None`.
+    Collision {
+        src: PathBuf,
+        dst: PathBuf,
+        other: Option<PathBuf>,
+    },
+    /// `src` and `other` would be moved to names that only differ in their
+    /// Unicode normalization, which some filesystems take for the same name.
+    /// `dst` is that name, composed.
+    Normalization {
+        src: PathBuf,
+        dst: PathBuf,
+        other: PathBuf,
+    },
+    /// `git mv` could not be run or failed.
+    Git { path: PathBuf, message: String },
+    /// The undo journal is unreadable, or an operation recorded in it can no
+    /// longer be undone.
+    Journal { path: PathBuf, message: String },
+    /// The user could not be prompted by an interactive rule.
+    Terminal(io::Error),
+}
+
+impl OcdError {
+    pub fn filesystem(path: &Path, source: io::Error) -> OcdError {
+        OcdError::Filesystem {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    /// The process exit code for the error, distinct for each kind of error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            OcdError::Lex(_) => 2,
+            OcdError::Parse { .. } => 3,
+            OcdError::Rule { .. } => 4,
+            OcdError::Filesystem { .. } => 5,
+            OcdError::Collision { .. } | OcdError::Normalization { .. } => 6,
+            OcdError::Git { .. } => 7,
+            OcdError::Journal { .. } => 8,
+            OcdError::Terminal(_) => 9,
+        }
+    }
+}
+
+impl fmt::Display for OcdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OcdError::Lex(error) => write!(f, "{}", error),
+            OcdError::Parse {
+                message,
+                diagnostic: None,
+            } => write!(f, "{}", message),
+            OcdError::Parse {
+                message,
+                diagnostic: Some(diagnostic),
+            } => write!(f, "{}\n{}", message, diagnostic),
+            OcdError::Rule {
+                rule,
+                path,
+                message,
+            } => write!(f, "Error applying {} to {:?}: {}", rule, path, message),
+            OcdError::Filesystem { path, source } => write!(f, "Error with {:?}: {}", path, source),
+            OcdError::Collision {
+                src,
+                dst,
+                other: Some(other),
+            } => write!(
+                f,
+                "Error: both {:?} and {:?} would be moved to {:?}",
+                other, src, dst
+            ),
+            OcdError::Collision {
+                src,
+                dst,
+                other: None,
+            } => write!(
+                f,
+                "Error: moving {:?} would overwrite the existing {:?}",
+                src, dst
+            ),
+            OcdError::Normalization { src, dst, other } => write!(
+                f,
+                "Error: {:?} and {:?} would both be named {:?} once normalized",
+                other, src, dst
+            ),
+            OcdError::Git { path, message } => {
+                write!(f, "Error moving {:?} with git: {}", path, message)
+            }
+            OcdError::Journal { path, message } => {
+                write!(f, "Unable to undo {:?}: {}", path, message)
+            }
+            OcdError::Terminal(source) => {
+                write!(f, "Interactive rules need a terminal: {}", source)
+            }
+        }
+    }
+}
+
+impl Error for OcdError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            OcdError::Lex(error) => Some(error),
+            OcdError::Filesystem { source, .. } => Some(source),
+            OcdError::Terminal(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<TokenizerError> for OcdError {
+    fn from(error: TokenizerError) -> OcdError {
+        OcdError::Lex(error)
+    }
+}
@@ -1,4 +1,5 @@
-use dialoguer::Confirmation;
+use dialoguer::{Confirmation, Input, Select};
+use std::io;
 
 pub fn user_confirm() -> bool {
     Confirmation::new()
@@ -6,3 +7,37 @@ pub fn user_confirm() -> bool {
         .interact()
         .unwrap_or(false)
 }
+
+/// Asks for a line of text, offering `default` when it is not empty.
+pub fn user_text(prompt: &str, default: &str) -> io::Result<String> {
+    let mut input = Input::<String>::new();
+    input.with_prompt(prompt);
+    if !default.is_empty() {
+        input.default(String::from(default));
+    }
+    input.interact()
+}
+
+/// What to do with a rule built interactively, once its effect has been
+/// previewed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Choice {
+    Accept,
+    Edit,
+    Skip,
+}
+
+pub fn user_choice() -> io::Result<Choice> {
+    let choices = [Choice::Accept, Choice::Edit, Choice::Skip];
+    let selected = user_select("What do you want to do?", &["Accept", "Edit", "Skip"], 0)?;
+    Ok(choices[selected])
+}
+
+/// Asks to pick one of `items`, returning its index.
+pub fn user_select(prompt: &str, items: &[&str], default: usize) -> io::Result<usize> {
+    Select::new()
+        .with_prompt(prompt)
+        .items(items)
+        .default(default)
+        .interact()
+}
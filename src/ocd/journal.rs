@@ -0,0 +1,243 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// One line of the undo journal.
+///
+/// Entries written by the same invocation of ocd share the same `run`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Entry {
+    pub run: String,
+    pub command: String,
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub operation: Operation,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "operation", rename_all = "snake_case")]
+pub enum Operation {
+    Move {
+        src: PathBuf,
+        dst: PathBuf,
+        git: bool,
+        metadata: FileMetadata,
+    },
+    CreateDirectory {
+        path: PathBuf,
+    },
+}
+
+/// The state of a file right after ocd moved it, used to detect whether it
+/// was modified before an undo.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct FileMetadata {
+    pub is_dir: bool,
+    pub size: u64,
+    pub modified: Option<String>,
+}
+
+impl FileMetadata {
+    pub fn read(path: &Path) -> io::Result<FileMetadata> {
+        let metadata = fs::symlink_metadata(path)?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .map(|time| DateTime::<Local>::from(time).to_rfc3339());
+        Ok(FileMetadata {
+            is_dir: metadata.is_dir(),
+            size: metadata.len(),
+            modified,
+        })
+    }
+}
+
+/// Collects the operations performed by one run and appends them to the
+/// journal file.
+pub struct Journal {
+    path: PathBuf,
+    run: String,
+    command: String,
+    entries: Vec<Entry>,
+}
+
+impl Journal {
+    pub fn new(path: &Path, command: &str) -> Journal {
+        Journal {
+            path: path.to_path_buf(),
+            run: Local::now().to_rfc3339(),
+            command: String::from(command),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Records a move that has already happened, reading the metadata of the
+    /// file at its new location.
+    pub fn record_move(&mut self, src: &Path, dst: &Path, git: bool) -> io::Result<()> {
+        let metadata = FileMetadata::read(dst)?;
+        self.record_move_with(src, dst, git, metadata)
+    }
+
+    /// Records a move that is about to happen, reading the metadata of the
+    /// file at its old location, which a rename doesn't change. Nothing is
+    /// then left moved without an entry if reading it fails.
+    pub fn record_pending_move(&mut self, src: &Path, dst: &Path, git: bool) -> io::Result<()> {
+        let metadata = FileMetadata::read(src)?;
+        self.record_move_with(src, dst, git, metadata)
+    }
+
+    fn record_move_with(
+        &mut self,
+        src: &Path,
+        dst: &Path,
+        git: bool,
+        metadata: FileMetadata,
+    ) -> io::Result<()> {
+        self.record(Operation::Move {
+            src: absolute(src)?,
+            dst: absolute(dst)?,
+            git,
+            metadata,
+        });
+        Ok(())
+    }
+
+    pub fn record_create_directory(&mut self, path: &Path) -> io::Result<()> {
+        self.record(Operation::CreateDirectory {
+            path: absolute(path)?,
+        });
+        Ok(())
+    }
+
+    fn record(&mut self, operation: Operation) {
+        self.entries.push(Entry {
+            run: self.run.clone(),
+            command: self.command.clone(),
+            timestamp: Local::now().to_rfc3339(),
+            operation,
+        });
+    }
+
+    /// Appends the recorded entries to the journal file.
+    pub fn commit(&mut self) -> io::Result<()> {
+        if self.entries.is_empty() {
+            return Ok(());
+        }
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        for entry in self.entries.drain(..) {
+            writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        }
+        Ok(())
+    }
+}
+
+/// Paths are stored absolute so that `ocd undo` can be run from anywhere, and
+/// without `.` components so that the same path is always written the same
+/// way.
+fn absolute(path: &Path) -> io::Result<PathBuf> {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()?.join(path)
+    };
+    // Collecting the components drops the `.` ones inside the path.
+    Ok(path.components().collect())
+}
+
+/// Reads every entry in the journal file, oldest first. A missing journal is
+/// treated as an empty one.
+pub fn load(path: &Path) -> io::Result<Vec<Entry>> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(reason) if reason.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(reason) => return Err(reason),
+    };
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            entries.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(entries)
+}
+
+/// Replaces the contents of the journal file with the given entries.
+pub fn save(path: &Path, entries: &[Entry]) -> io::Result<()> {
+    let mut contents = String::new();
+    for entry in entries {
+        contents.push_str(&serde_json::to_string(entry)?);
+        contents.push('\n');
+    }
+    fs::write(path, contents)
+}
+
+/// The default location of the journal, `$XDG_DATA_HOME/ocd/journal.jsonl` or
+/// its platform equivalent.
+pub fn default_path() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("ocd")
+        .join("journal.jsonl")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn commit_and_load_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ocd").join("journal.jsonl");
+        let file = dir.path().join("b");
+        fs::write(&file, "contents").unwrap();
+
+        let mut journal = Journal::new(&path, "mrn");
+        journal.record_create_directory(dir.path()).unwrap();
+        journal
+            .record_move(&dir.path().join("a"), &file, false)
+            .unwrap();
+        journal.commit().unwrap();
+        let mut journal = Journal::new(&path, "tss");
+        journal.record_create_directory(dir.path()).unwrap();
+        journal.commit().unwrap();
+
+        let entries = load(&path).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].command, "mrn");
+        assert_eq!(entries[0].run, entries[1].run);
+        assert_eq!(entries[2].command, "tss");
+        match &entries[1].operation {
+            Operation::Move { dst, metadata, .. } => {
+                assert_eq!(dst, &file);
+                assert_eq!(metadata, &FileMetadata::read(&file).unwrap());
+                assert_eq!(metadata.size, 8);
+            }
+            operation => panic!("unexpected operation {:?}", operation),
+        }
+    }
+
+    #[test]
+    fn absolute_test() {
+        let path = Path::new("/tmp/ut/./dd/.");
+        assert_eq!(absolute(path).unwrap(), PathBuf::from("/tmp/ut/dd"));
+        let relative = absolute(Path::new("./a")).unwrap();
+        assert_eq!(relative, std::env::current_dir().unwrap().join("a"));
+    }
+
+    #[test]
+    fn load_missing_test() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(load(&dir.path().join("journal.jsonl")).unwrap().is_empty());
+    }
+}
@@ -1,11 +1,18 @@
 pub mod config;
+pub mod error;
 pub mod input;
+pub mod journal;
 pub mod mrn;
 pub mod output;
+pub mod settings;
+pub mod timestamp;
 pub mod tss;
+pub mod undo;
 
 use crate::ocd::mrn::MassRenameConfig;
+use crate::ocd::settings::Setting;
 use crate::ocd::tss::TimeStampSortConfig;
+use crate::ocd::undo::UndoConfig;
 
 /// The Command enum represents which subcommand ocd will run and carries its
 /// configuration with it.
@@ -13,7 +20,9 @@ use crate::ocd::tss::TimeStampSortConfig;
 #[derive(Clone, Debug)]
 pub enum Command {
     MassRename { config: MassRenameConfig },
+    PrintConfig { settings: Vec<Setting> },
     TimeStampSort { config: TimeStampSortConfig },
+    Undo { config: UndoConfig },
     // FixID3 { config: FixID3Config },
     // ElephantClient{ config: ElephantClientConfig },
     // ElephantServer{ config: ElephantServerConfig },
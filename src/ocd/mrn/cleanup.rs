@@ -0,0 +1,107 @@
+/// The characters that the trim and collapse rules work on.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Class {
+    /// Spaces, tabs and any other whitespace.
+    Space,
+    Dash,
+    Period,
+    Under,
+    /// Whitespace, dashes, periods and underscores, the separators of the
+    /// replace rules.
+    Separator,
+    /// Punctuation other than brackets, which frame a part of the name.
+    Punctuation,
+}
+
+pub const CLASSES: [(&str, Class); 6] = [
+    ("space", Class::Space),
+    ("dash", Class::Dash),
+    ("period", Class::Period),
+    ("under", Class::Under),
+    ("separator", Class::Separator),
+    ("punctuation", Class::Punctuation),
+];
+
+/// Brackets are left alone so that a name doesn't lose the end of a
+/// `(Live)` or `[2019]`.
+const BRACKETS: &[char] = &['(', ')', '[', ']', '{', '}', '<', '>'];
+
+/// Punctuation outside of ASCII that is common in names.
+const PUNCTUATION: &[char] = &[
+    '–', '—', '…', '·', '•', '¡', '¿', '«', '»', '“', '”', '‘', '’',
+];
+
+impl Class {
+    pub fn find(name: &str) -> Option<Class> {
+        CLASSES
+            .iter()
+            .find(|(class, _)| *class == name)
+            .map(|(_, class)| *class)
+    }
+
+    pub fn contains(self, c: char) -> bool {
+        match self {
+            Class::Space => c.is_whitespace(),
+            Class::Dash => c == '-',
+            Class::Period => c == '.',
+            Class::Under => c == '_',
+            Class::Separator => c.is_whitespace() || matches!(c, '-' | '.' | '_'),
+            Class::Punctuation => {
+                (c.is_ascii_punctuation() && !BRACKETS.contains(&c)) || PUNCTUATION.contains(&c)
+            }
+        }
+    }
+}
+
+/// Removes the characters of `class` from the start and the end of the name.
+pub fn trim(filename: &str, class: Class) -> String {
+    String::from(filename.trim_matches(|c| class.contains(c)))
+}
+
+/// Replaces every run of characters of `class` with its first character, so
+/// `a -- b` becomes `a - b` for dashes and `a b` for separators.
+pub fn collapse(filename: &str, class: Class) -> String {
+    let mut collapsed = String::with_capacity(filename.len());
+    let mut previous = None;
+    for c in filename.chars() {
+        let repeated = previous.is_some_and(|previous| class.contains(previous));
+        if !(repeated && class.contains(c)) {
+            collapsed.push(c);
+        }
+        previous = Some(c);
+    }
+    collapsed
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn trim_test() {
+        assert_eq!(trim(" \tName  ", Class::Space), "Name");
+        assert_eq!(trim("--Name..", Class::Punctuation), "Name");
+        assert_eq!(trim("¿Qué? (Live)…", Class::Punctuation), "Qué? (Live)");
+        assert_eq!(trim("_a_b_", Class::Under), "a_b");
+        assert_eq!(trim("...", Class::Period), "");
+    }
+
+    #[test]
+    fn collapse_test() {
+        assert_eq!(collapse("a--b---c", Class::Dash), "a-b-c");
+        assert_eq!(collapse("a -- b", Class::Dash), "a - b");
+        assert_eq!(collapse("a -- b", Class::Separator), "a b");
+        assert_eq!(collapse("a  \t b", Class::Space), "a b");
+        assert_eq!(
+            collapse("Wow!!! Really??", Class::Punctuation),
+            "Wow! Really?"
+        );
+        assert_eq!(collapse("a..b__c", Class::Period), "a.b__c");
+    }
+
+    #[test]
+    fn find_test() {
+        assert_eq!(Class::find("dash"), Some(Class::Dash));
+        assert_eq!(Class::find("dashes"), None);
+    }
+}
@@ -0,0 +1,225 @@
+use crate::ocd::mrn::extensions;
+use crate::ocd::mrn::RuleRegex;
+use chrono::{DateTime, Local, NaiveDate};
+use std::fs;
+use std::path::Path;
+
+/// What a conditional rule checks about an entry before applying its rules.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+    Not(Box<Condition>),
+    /// The current extension of the name, ignoring case.
+    Extension(String),
+    Directory,
+    File,
+    /// A regex that matches the current name, extension included.
+    Name(RuleRegex),
+    Size {
+        comparison: Comparison,
+        bytes: u64,
+    },
+    Modified {
+        comparison: Comparison,
+        date: NaiveDate,
+    },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    fn holds<T: PartialOrd>(self, a: T, b: T) -> bool {
+        match self {
+            Comparison::Less => a < b,
+            Comparison::LessOrEqual => a <= b,
+            Comparison::Equal => a == b,
+            Comparison::GreaterOrEqual => a >= b,
+            Comparison::Greater => a > b,
+        }
+    }
+}
+
+/// How conditions are written, for the lexer to tell them from rules and for
+/// error messages.
+pub const USAGE: &str =
+    "conditions are dir, file, ext=<extension>, size<10M, mtime>=2020-01-01 or /<regex>/, negated with !";
+
+/// The conditions written as a word followed by a comparison.
+const COMPARED: &[&str] = &["ext", "size", "mtime"];
+
+/// Whether the lexer should read `word` as a condition.
+pub fn is_condition(word: &str) -> bool {
+    word == "dir"
+        || word == "file"
+        || COMPARED.iter().any(|name| {
+            word.strip_prefix(name)
+                .is_some_and(|rest| rest.starts_with(['<', '=', '>']))
+        })
+}
+
+impl Condition {
+    /// Reads a condition other than a regex, as written in the rules.
+    pub fn parse(word: &str) -> Result<Condition, String> {
+        match word {
+            "dir" => return Ok(Condition::Directory),
+            "file" => return Ok(Condition::File),
+            _ => {}
+        }
+        let split = word
+            .find(['<', '=', '>'])
+            .ok_or_else(|| format!("unknown condition {:?}", word))?;
+        let (name, rest) = word.split_at(split);
+        let (comparison, value) = if let Some(value) = rest.strip_prefix("<=") {
+            (Comparison::LessOrEqual, value)
+        } else if let Some(value) = rest.strip_prefix(">=") {
+            (Comparison::GreaterOrEqual, value)
+        } else if let Some(value) = rest.strip_prefix('<') {
+            (Comparison::Less, value)
+        } else if let Some(value) = rest.strip_prefix('>') {
+            (Comparison::Greater, value)
+        } else {
+            (Comparison::Equal, &rest[1..])
+        };
+        match name {
+            "ext" if comparison == Comparison::Equal => {
+                Ok(Condition::Extension(value.to_lowercase()))
+            }
+            "ext" => Err(String::from("an extension can only be compared with =")),
+            "size" => Ok(Condition::Size {
+                comparison,
+                bytes: parse_size(value)?,
+            }),
+            "mtime" => Ok(Condition::Modified {
+                comparison,
+                date: NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                    .map_err(|_| format!("invalid date {:?}, expected YYYY-MM-DD", value))?,
+            }),
+            _ => Err(format!("unknown condition {:?}", word)),
+        }
+    }
+
+    /// Whether the entry at `src`, currently named `path`, meets the
+    /// condition. The `compound` extensions are compared whole.
+    pub fn holds(&self, src: &Path, path: &Path, compound: &[String]) -> Result<bool, String> {
+        let metadata = || {
+            fs::metadata(src).map_err(|reason| format!("unable to read the metadata: {}", reason))
+        };
+        match self {
+            Condition::Not(condition) => condition.holds(src, path, compound).map(|holds| !holds),
+            Condition::Extension(extension) => {
+                Ok(extensions::has_extension(path, extension, compound))
+            }
+            Condition::Directory => Ok(metadata()?.is_dir()),
+            Condition::File => Ok(metadata()?.is_file()),
+            Condition::Name(regex) => Ok(path
+                .file_name()
+                .is_some_and(|name| regex.0.is_match(&name.to_string_lossy()))),
+            Condition::Size { comparison, bytes } => {
+                Ok(comparison.holds(metadata()?.len(), *bytes))
+            }
+            Condition::Modified { comparison, date } => {
+                let modified = metadata()?.modified().map_err(|reason| {
+                    format!("unable to read the modification time: {}", reason)
+                })?;
+                let modified = DateTime::<Local>::from(modified).naive_local().date();
+                Ok(comparison.holds(modified, *date))
+            }
+        }
+    }
+}
+
+/// Reads a size in bytes, optionally followed by K, M or G for powers of 1024.
+fn parse_size(size: &str) -> Result<u64, String> {
+    let digits = size.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let multiplier = match size[digits.len()..].to_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        _ => {
+            return Err(format!(
+                "invalid size {:?}, expected e.g. 500, 10K or 2M",
+                size
+            ))
+        }
+    };
+    let value = digits
+        .parse::<u64>()
+        .map_err(|_| format!("invalid size {:?}, expected e.g. 500, 10K or 2M", size))?;
+    value
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size {:?} is too large", size))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn parse_test() {
+        assert!(is_condition("ext=mp3"));
+        assert!(is_condition("size>=1"));
+        assert!(is_condition("dir"));
+        assert!(!is_condition("extra"));
+        assert!(!is_condition("d"));
+        assert_eq!(
+            Condition::parse("ext=MP3"),
+            Ok(Condition::Extension(String::from("mp3")))
+        );
+        assert_eq!(
+            Condition::parse("size>10K"),
+            Ok(Condition::Size {
+                comparison: Comparison::Greater,
+                bytes: 10240
+            })
+        );
+        assert_eq!(
+            Condition::parse("mtime<=2020-01-31"),
+            Ok(Condition::Modified {
+                comparison: Comparison::LessOrEqual,
+                date: NaiveDate::from_ymd_opt(2020, 1, 31).unwrap()
+            })
+        );
+        assert!(Condition::parse("size>10X").is_err());
+        assert_eq!(
+            Condition::parse("size>99999999999G"),
+            Err(String::from("size \"99999999999G\" is too large"))
+        );
+        assert!(Condition::parse("mtime>2020-13-01").is_err());
+        assert!(Condition::parse("ext<mp3").is_err());
+    }
+
+    #[test]
+    fn holds_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("song.MP3");
+        fs::write(&src, "12345").unwrap();
+        let renamed = PathBuf::from("Song.mp3");
+        let holds = |condition: &str| {
+            Condition::parse(condition)
+                .unwrap()
+                .holds(&src, &renamed, &[])
+        };
+        assert_eq!(holds("ext=mp3"), Ok(true));
+        assert_eq!(holds("ext=flac"), Ok(false));
+        assert_eq!(holds("file"), Ok(true));
+        assert_eq!(holds("dir"), Ok(false));
+        assert_eq!(holds("size=5"), Ok(true));
+        assert_eq!(holds("size>1K"), Ok(false));
+        assert_eq!(holds("mtime>2000-01-01"), Ok(true));
+        let name = Condition::Name(RuleRegex(regex::Regex::new("^S").unwrap()));
+        assert_eq!(name.holds(&src, &renamed, &[]), Ok(true));
+        let not = Condition::Not(Box::new(Condition::Directory));
+        assert_eq!(not.holds(dir.path(), dir.path(), &[]), Ok(false));
+        assert!(Condition::File
+            .holds(&dir.path().join("missing"), &renamed, &[])
+            .is_err());
+    }
+}
@@ -0,0 +1,147 @@
+use std::fmt;
+
+/// Points at the part of the rules that an error is about, and optionally
+/// suggests how to fix it.
+///
+/// Offsets and lengths are counted in characters, not bytes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub input: String,
+    pub offset: usize,
+    pub length: usize,
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(input: &str, offset: usize, length: usize) -> Diagnostic {
+        Diagnostic {
+            input: String::from(input),
+            offset,
+            length,
+            hint: None,
+        }
+    }
+
+    pub fn with_hint(mut self, hint: Option<String>) -> Diagnostic {
+        self.hint = hint;
+        self
+    }
+}
+
+/// Renders the rules with carets under the offending characters:
+///
+/// ```text
+///     lc,sx
+///        ^^
+///     hint: did you mean `sd`?
+/// ```
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "    {}", self.input)?;
+        write!(
+            f,
+            "    {}{}",
+            " ".repeat(self.offset),
+            "^".repeat(self.length.max(1))
+        )?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n    hint: {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+/// Suggests the candidates closest to a misspelled word, as in
+/// "did you mean `sd`?". A word that only differs from a candidate by its
+/// case gets that candidate. Otherwise only candidates within two edits of the word, and
+/// fewer edits than its length, are considered, so that a stray character is
+/// not matched with every short keyword.
+pub fn did_you_mean(word: &str, candidates: &[&str]) -> Option<String> {
+    let lowercase = word.to_lowercase();
+    if lowercase != word && candidates.contains(&lowercase.as_str()) {
+        return Some(format!("did you mean `{}`?", lowercase));
+    }
+    let limit = 2.min(word.chars().count().saturating_sub(1));
+    let scored: Vec<(usize, &str)> = candidates
+        .iter()
+        .map(|candidate| (edit_distance(word, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .collect();
+    let best = scored.iter().map(|(distance, _)| *distance).min()?;
+    let length = word.chars().count();
+    let mut closest: Vec<&str> = scored
+        .iter()
+        .filter(|(distance, _)| *distance == best)
+        .map(|(_, candidate)| *candidate)
+        .collect();
+    // Keywords of the same length are the likeliest typos.
+    if closest
+        .iter()
+        .any(|candidate| candidate.chars().count() == length)
+    {
+        closest.retain(|candidate| candidate.chars().count() == length);
+    }
+    closest.sort_unstable();
+    let quoted: Vec<String> = closest
+        .iter()
+        .map(|candidate| format!("`{}`", candidate))
+        .collect();
+    Some(match quoted.split_last() {
+        Some((last, [])) => format!("did you mean {}?", last),
+        Some((last, rest)) => format!("did you mean {} or {}?", rest.join(", "), last),
+        None => unreachable!(),
+    })
+}
+
+/// The Levenshtein distance between two words, in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_test() {
+        let diagnostic =
+            Diagnostic::new("lc,sx", 3, 2).with_hint(Some(String::from("did you mean `sd`?")));
+        assert_eq!(
+            diagnostic.to_string(),
+            "    lc,sx\n       ^^\n    hint: did you mean `sd`?"
+        );
+        assert_eq!(
+            Diagnostic::new("lc,", 3, 0).to_string(),
+            "    lc,\n       ^"
+        );
+    }
+
+    #[test]
+    fn did_you_mean_test() {
+        let keywords = ["s", "sc", "sd", "sp", "su", "snc", "lc", "uc"];
+        assert_eq!(
+            did_you_mean("sdd", &keywords),
+            Some(String::from("did you mean `sd`?"))
+        );
+        assert_eq!(
+            did_you_mean("Lc", &keywords),
+            Some(String::from("did you mean `lc`?"))
+        );
+        assert_eq!(
+            did_you_mean("sx", &keywords),
+            Some(String::from("did you mean `sc`, `sd`, `sp` or `su`?"))
+        );
+        assert_eq!(did_you_mean("#", &keywords), None);
+        assert_eq!(did_you_mean("qqqq", &keywords), None);
+    }
+}
@@ -0,0 +1,362 @@
+use crate::ocd::error::OcdError;
+use crate::ocd::journal::Journal;
+use crate::ocd::mrn::MassRenameConfig;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A single filesystem rename, as scheduled by the planner.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Move {
+    pub src: PathBuf,
+    pub dst: PathBuf,
+}
+
+/// Validates the clean buffer and orders its entries into a sequence of moves
+/// that can be executed one at a time without overwriting anything.
+///
+/// Fails if two sources share a destination or if a destination exists on disk
+/// and is not itself going to be renamed away. Renames that form chains are
+/// ordered so every destination is free by the time it is used, and cycles
+/// (`a -> b, b -> a`) are broken by moving one entry to a temporary name first.
+pub fn plan(buffer: &BTreeMap<PathBuf, PathBuf>) -> Result<Vec<Move>, OcdError> {
+    check_destinations(buffer)?;
+
+    let mut pending = buffer.clone();
+    let mut moves = Vec::new();
+    // Walk from the last entry so that, when recursing, children are renamed
+    // before the directories that contain them.
+    while let Some(start) = pending.keys().next_back().cloned() {
+        // Follow the chain of renames until reaching a destination that is
+        // not waiting to be moved away. Destinations are unique, so if the
+        // chain loops it can only loop back to its start.
+        let mut chain = vec![start.clone()];
+        let mut cycle = false;
+        loop {
+            let dst = &pending[chain.last().unwrap()];
+            if *dst == start {
+                cycle = true;
+                break;
+            } else if pending.contains_key(dst) {
+                chain.push(dst.clone());
+            } else {
+                break;
+            }
+        }
+
+        if cycle {
+            let temporary = temporary_name(&start, buffer);
+            let dst = pending.remove(&start).unwrap();
+            moves.push(Move {
+                src: start.clone(),
+                dst: temporary.clone(),
+            });
+            for src in chain.iter().skip(1).rev() {
+                let dst = pending.remove(src).unwrap();
+                moves.push(Move {
+                    src: src.clone(),
+                    dst,
+                });
+            }
+            moves.push(Move {
+                src: temporary,
+                dst,
+            });
+        } else {
+            for src in chain.iter().rev() {
+                let dst = pending.remove(src).unwrap();
+                moves.push(Move {
+                    src: src.clone(),
+                    dst,
+                });
+            }
+        }
+    }
+    Ok(moves)
+}
+
+/// Performs the planned moves in order. If one of them fails, the moves that
+/// were already made are undone in reverse order before returning the error.
+/// Once every move has succeeded they are recorded in the undo journal.
+pub fn execute(config: &MassRenameConfig, moves: &[Move]) -> Result<(), OcdError> {
+    let mut journal = Journal::new(&config.journal, "mrn");
+    for (done, Move { src, dst }) in moves.iter().enumerate() {
+        crate::ocd::output::file_move(config.verbosity, src, dst);
+        if config.dryrun {
+            continue;
+        }
+        // The move is recorded first, so that a failure leaves only the
+        // moves before it to roll back. The journal is not written then.
+        let result = journal
+            .record_pending_move(src, dst, config.git)
+            .map_err(|reason| OcdError::filesystem(src, reason))
+            .and_then(|_| rename(config, src, dst));
+        if let Err(error) = result {
+            rollback(config, &moves[..done]);
+            return Err(error);
+        }
+    }
+    journal
+        .commit()
+        .map_err(|reason| OcdError::filesystem(&config.journal, reason))
+}
+
+fn rollback(config: &MassRenameConfig, moves: &[Move]) {
+    for Move { src, dst } in moves.iter().rev() {
+        crate::ocd::output::file_rollback(config.verbosity, dst, src);
+        if let Err(reason) = rename(config, dst, src) {
+            eprintln!("Error rolling back {:?} to {:?}: {}", dst, src, reason);
+        }
+    }
+    crate::ocd::output::rollback_done(config.verbosity, moves.len());
+}
+
+fn rename(config: &MassRenameConfig, src: &Path, dst: &Path) -> Result<(), OcdError> {
+    if config.git {
+        let git_error = |message| OcdError::Git {
+            path: src.to_path_buf(),
+            message,
+        };
+        let output = Command::new("git")
+            .arg("mv")
+            .arg(src)
+            .arg(dst)
+            .output()
+            .map_err(|reason| git_error(format!("unable to invoke git: {}", reason)))?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(git_error(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ))
+        }
+    } else {
+        fs::rename(src, dst).map_err(|reason| OcdError::filesystem(src, reason))
+    }
+}
+
+fn check_destinations(buffer: &BTreeMap<PathBuf, PathBuf>) -> Result<(), OcdError> {
+    let mut destinations: BTreeMap<&PathBuf, &PathBuf> = BTreeMap::new();
+    for (src, dst) in buffer {
+        if let Some(other) = destinations.insert(dst, src) {
+            return Err(OcdError::Collision {
+                src: src.clone(),
+                dst: dst.clone(),
+                other: Some(other.clone()),
+            });
+        }
+        if exists(dst) && !buffer.contains_key(dst) && !same_file(src, dst) {
+            return Err(OcdError::Collision {
+                src: src.clone(),
+                dst: dst.clone(),
+                other: None,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Picks a name next to `path` that is not on disk and not used by the buffer.
+fn temporary_name(path: &Path, buffer: &BTreeMap<PathBuf, PathBuf>) -> PathBuf {
+    let used: BTreeSet<&PathBuf> = buffer.keys().chain(buffer.values()).collect();
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    (0..)
+        .map(|n| path.with_file_name(format!(".{}.ocd-{}", name, n)))
+        .find(|candidate| !used.contains(candidate) && !exists(candidate))
+        .unwrap()
+}
+
+/// Whether `a` and `b` name the same entry, as a name and the same name in
+/// another case do on case-insensitive filesystems. Renaming a file to such a
+/// name overwrites nothing.
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::symlink_metadata(a), fs::symlink_metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(a: &Path, b: &Path) -> bool {
+    a.to_string_lossy().to_lowercase() == b.to_string_lossy().to_lowercase()
+}
+
+fn exists(path: &Path) -> bool {
+    // symlink_metadata also reports broken symlinks, which fs::rename would
+    // happily replace.
+    fs::symlink_metadata(path).is_ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ocd::config::Verbosity;
+
+    fn config(dir: &Path) -> MassRenameConfig {
+        let mut config = MassRenameConfig::new();
+        config.verbosity = Verbosity::Silent;
+        config.dryrun = false;
+        config.journal = dir.join("journal.jsonl");
+        config
+    }
+
+    fn buffer(dir: &Path, renames: &[(&str, &str)]) -> BTreeMap<PathBuf, PathBuf> {
+        renames
+            .iter()
+            .map(|(src, dst)| (dir.join(src), dir.join(dst)))
+            .collect()
+    }
+
+    fn touch(dir: &Path, names: &[&str]) {
+        for name in names {
+            fs::write(dir.join(name), name).unwrap();
+        }
+    }
+
+    fn contents(dir: &Path, name: &str) -> String {
+        fs::read_to_string(dir.join(name)).unwrap()
+    }
+
+    #[test]
+    fn plan_chain_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        touch(dir, &["a", "b"]);
+        let moves = plan(&buffer(dir, &[("a", "b"), ("b", "c")])).unwrap();
+        assert_eq!(
+            moves,
+            vec![
+                Move {
+                    src: dir.join("b"),
+                    dst: dir.join("c")
+                },
+                Move {
+                    src: dir.join("a"),
+                    dst: dir.join("b")
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn plan_duplicate_destination_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        touch(dir, &["a", "b"]);
+        match plan(&buffer(dir, &[("a", "c"), ("b", "c")])) {
+            Err(OcdError::Collision {
+                src,
+                dst,
+                other: Some(other),
+            }) => {
+                assert_eq!(other, dir.join("a"));
+                assert_eq!(src, dir.join("b"));
+                assert_eq!(dst, dir.join("c"));
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn plan_existing_destination_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        touch(dir, &["a", "b"]);
+        let error = plan(&buffer(dir, &[("a", "b")])).unwrap_err();
+        assert!(matches!(error, OcdError::Collision { other: None, .. }));
+        assert_eq!(error.exit_code(), 6);
+    }
+
+    #[test]
+    fn plan_same_file_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        touch(dir, &["AAA"]);
+        // A hard link stands in for the same name in another case on a
+        // case-insensitive filesystem.
+        fs::hard_link(dir.join("AAA"), dir.join("aaa")).unwrap();
+        let moves = plan(&buffer(dir, &[("AAA", "aaa")])).unwrap();
+        assert_eq!(moves.len(), 1);
+    }
+
+    #[test]
+    fn execute_swap_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        touch(dir, &["a", "b"]);
+        let moves = plan(&buffer(dir, &[("a", "b"), ("b", "a")])).unwrap();
+        assert_eq!(moves.len(), 3);
+        execute(&config(dir), &moves).unwrap();
+        assert_eq!(contents(dir, "a"), "b");
+        assert_eq!(contents(dir, "b"), "a");
+        assert!(!dir.join(".a.ocd-0").exists());
+        let journal = crate::ocd::journal::load(&dir.join("journal.jsonl")).unwrap();
+        assert_eq!(journal.len(), 3);
+    }
+
+    #[test]
+    fn execute_rotation_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        touch(dir, &["a", "b", "c"]);
+        let moves = plan(&buffer(dir, &[("a", "b"), ("b", "c"), ("c", "a")])).unwrap();
+        execute(&config(dir), &moves).unwrap();
+        assert_eq!(contents(dir, "a"), "c");
+        assert_eq!(contents(dir, "b"), "a");
+        assert_eq!(contents(dir, "c"), "b");
+    }
+
+    #[test]
+    fn execute_rollback_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        touch(dir, &["a", "b"]);
+        let moves = vec![
+            Move {
+                src: dir.join("a"),
+                dst: dir.join("c"),
+            },
+            Move {
+                src: dir.join("b"),
+                dst: dir.join("missing").join("d"),
+            },
+        ];
+        match execute(&config(dir), &moves) {
+            Err(OcdError::Filesystem { path, .. }) => assert_eq!(path, dir.join("b")),
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert!(dir.join("a").exists());
+        assert!(!dir.join("c").exists());
+        assert!(!dir.join("journal.jsonl").exists());
+    }
+
+    #[test]
+    fn execute_journal_error_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        touch(dir, &["a"]);
+        let moves = vec![
+            Move {
+                src: dir.join("a"),
+                dst: dir.join("c"),
+            },
+            Move {
+                src: dir.join("gone"),
+                dst: dir.join("d"),
+            },
+        ];
+        match execute(&config(dir), &moves) {
+            Err(OcdError::Filesystem { path, .. }) => assert_eq!(path, dir.join("gone")),
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert!(dir.join("a").exists());
+        assert!(!dir.join("c").exists());
+        assert!(!dir.join("d").exists());
+    }
+}
@@ -0,0 +1,192 @@
+use infer::MatcherType;
+use std::path::{Path, PathBuf};
+
+/// The extensions made of several parts that are kept whole unless others are
+/// configured.
+pub const COMPOUND: &[&str] = &["tar.gz", "tar.bz2", "tar.xz", "tar.zst", "d.ts"];
+
+/// Extensions that name the same kind of file as the one detected from its
+/// contents.
+const EQUIVALENTS: &[(&str, &[&str])] = &[
+    ("jpg", &["jpeg", "jpe", "jfif"]),
+    ("tif", &["tiff"]),
+    ("mpg", &["mpeg"]),
+    ("midi", &["mid"]),
+    ("aiff", &["aif"]),
+    ("gz", &["tgz"]),
+    ("bz2", &["tbz", "tbz2"]),
+    ("xz", &["txz"]),
+];
+
+/// Formats that many others are built on, such as zip for comic books or
+/// Java archives. Their extension is only added to files that have none.
+const CONTAINERS: &[&str] = &["zip", "rar", "ogg", "mkv", "mp4"];
+
+/// Splits a file name into its stem and its extension. The longest of the
+/// `compound` extensions that ends the name, ignoring case, is kept whole.
+/// Otherwise the extension starts at the last dot, unless that dot starts the
+/// name.
+pub fn split<'a>(name: &'a str, compound: &[String]) -> (&'a str, Option<&'a str>) {
+    let dot = compound
+        .iter()
+        .filter_map(|extension| {
+            let dot = name.len().checked_sub(extension.len() + 1)?;
+            let matches = dot > 0
+                && name.is_char_boundary(dot)
+                && name[dot..].starts_with('.')
+                && name[dot + 1..].eq_ignore_ascii_case(extension);
+            Some(dot).filter(|_| matches)
+        })
+        .min()
+        .or_else(|| name.rfind('.').filter(|dot| *dot > 0));
+    match dot {
+        Some(dot) => (&name[..dot], Some(&name[dot + 1..])),
+        None => (name, None),
+    }
+}
+
+/// The stem and extension of the file name in `path`.
+fn split_path(path: &Path, compound: &[String]) -> (String, Option<String>) {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let (stem, extension) = split(&name, compound);
+    (String::from(stem), extension.map(String::from))
+}
+
+/// The file name in `path` without its extension.
+pub fn stem(path: &Path, compound: &[String]) -> String {
+    split_path(path, compound).0
+}
+
+/// The extension of the file name in `path`, if it has one.
+pub fn extension(path: &Path, compound: &[String]) -> Option<String> {
+    split_path(path, compound).1
+}
+
+/// Replaces the stem of the file name in `path`, keeping its extension.
+pub fn set_stem(path: &mut PathBuf, stem: &str, compound: &[String]) {
+    let name = match extension(path, compound) {
+        Some(extension) => format!("{}.{}", stem, extension),
+        None => String::from(stem),
+    };
+    path.set_file_name(name);
+}
+
+/// Replaces the extension of the file name in `path`, removing it if
+/// `extension` is empty.
+pub fn set_extension(path: &mut PathBuf, extension: &str, compound: &[String]) {
+    let stem = stem(path, compound);
+    let name = if extension.is_empty() {
+        stem
+    } else {
+        format!("{}.{}", stem, extension)
+    };
+    path.set_file_name(name);
+}
+
+/// Whether the current extension of `path` is `extension`, ignoring case.
+pub fn has_extension(path: &Path, extension: &str, compound: &[String]) -> bool {
+    self::extension(path, compound)
+        .is_some_and(|current| current.to_lowercase() == extension.to_lowercase())
+}
+
+/// The extension that the contents of the file at `src` call for, if the
+/// current one in `path` is missing or wrong. Directories, text files and
+/// executables are left alone, as are files of unknown formats. A compound
+/// extension is right if its last part is, e.g. `tar.gz` for gzip.
+pub fn detect(
+    src: &Path,
+    path: &Path,
+    compound: &[String],
+) -> Result<Option<&'static str>, String> {
+    if !src.is_file() {
+        return Ok(None);
+    }
+    let kind = match infer::get_from_path(src)
+        .map_err(|reason| format!("unable to read the contents: {}", reason))?
+    {
+        Some(kind) if !matches!(kind.matcher_type(), MatcherType::Text | MatcherType::App) => kind,
+        _ => return Ok(None),
+    };
+    let detected = kind.extension();
+    let current = match extension(path, compound) {
+        Some(current) => current.to_lowercase(),
+        None => return Ok(Some(detected)),
+    };
+    let last = current.rsplit('.').next().unwrap_or_default();
+    let equivalent = EQUIVALENTS
+        .iter()
+        .any(|(extension, others)| *extension == detected && others.contains(&last));
+    if last == detected || equivalent || CONTAINERS.contains(&detected) {
+        Ok(None)
+    } else {
+        Ok(Some(detected))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    const PNG: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 0];
+    const JPEG: &[u8] = &[0xFF, 0xD8, 0xFF, 0xE0, 0, 0, 0, 0];
+    const GZIP: &[u8] = &[0x1F, 0x8B, 0x08, 0, 0, 0, 0, 0];
+    const ZIP: &[u8] = &[b'P', b'K', 3, 4, 0, 0, 0, 0];
+
+    #[test]
+    fn detect_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let detect_as = |name: &str, contents: &[u8]| {
+            let src = dir.path().join(name);
+            fs::write(&src, contents).unwrap();
+            let compound = [String::from("tar.gz")];
+            detect(&src, &PathBuf::from(name), &compound).unwrap()
+        };
+        assert_eq!(detect_as("image", PNG), Some("png"));
+        assert_eq!(detect_as("image.jpg", PNG), Some("png"));
+        assert_eq!(detect_as("image.PNG", PNG), None);
+        assert_eq!(detect_as("photo.JPEG", JPEG), None);
+        assert_eq!(detect_as("comic.cbz", ZIP), None);
+        assert_eq!(detect_as("archive", ZIP), Some("zip"));
+        assert_eq!(detect_as("script", b"#!/bin/sh\necho"), None);
+        assert_eq!(detect_as("notes.txt", b"plain text"), None);
+        assert_eq!(detect_as("archive.tar.gz", GZIP), None);
+        assert_eq!(detect_as("archive.TGZ", GZIP), None);
+        assert_eq!(detect_as("image.tar.gz", PNG), Some("png"));
+        assert_eq!(detect(dir.path(), dir.path(), &[]), Ok(None));
+    }
+
+    #[test]
+    fn has_extension_test() {
+        let compound = [String::from("tar.gz")];
+        assert!(has_extension(Path::new("a.JPEG"), "jpeg", &[]));
+        assert!(!has_extension(Path::new("a.jpeg"), "jpg", &[]));
+        assert!(!has_extension(Path::new("jpeg"), "jpeg", &[]));
+        assert!(has_extension(Path::new("a.TAR.gz"), "tar.gz", &compound));
+        assert!(!has_extension(Path::new("a.tar.gz"), "gz", &compound));
+    }
+
+    #[test]
+    fn split_test() {
+        let compound: Vec<String> = ["gz", "tar.gz", "d.ts"]
+            .iter()
+            .map(|extension| String::from(*extension))
+            .collect();
+        assert_eq!(split("a.b.tar.gz", &compound), ("a.b", Some("tar.gz")));
+        assert_eq!(split("a.gz", &compound), ("a", Some("gz")));
+        assert_eq!(split("index.D.TS", &compound), ("index", Some("D.TS")));
+        assert_eq!(split("a.ts", &compound), ("a", Some("ts")));
+        assert_eq!(split(".tar.gz", &compound), (".tar", Some("gz")));
+        assert_eq!(split(".bashrc", &compound), (".bashrc", None));
+        assert_eq!(split("README", &compound), ("README", None));
+
+        let mut path = PathBuf::from("dir/a.b.tar.gz");
+        set_stem(&mut path, "A B.C", &compound);
+        assert_eq!(path, PathBuf::from("dir/A B.C.tar.gz"));
+        set_extension(&mut path, "tgz", &compound);
+        assert_eq!(path, PathBuf::from("dir/A B.C.tgz"));
+        set_extension(&mut path, "", &compound);
+        assert_eq!(path, PathBuf::from("dir/A B.C"));
+    }
+}
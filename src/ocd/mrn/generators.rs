@@ -0,0 +1,250 @@
+use chrono::format::{Item, StrftimeItems};
+use chrono::NaiveDateTime;
+use lazy_static::lazy_static;
+use rand::Rng;
+use regex::Regex;
+use std::path::Path;
+
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// The widest padding of a random number, enough for the largest one.
+const MAX_RANDOM_WIDTH: usize = 20;
+
+/// Replaces the date generators in a replace pattern.
+///
+/// `{date}`, `{year}`, `{month}`, `{monthname}`, `{monthsimp}`, `{day}`,
+/// `{dayname}` and `{daysimp}` are parts of the time the rules are applied at.
+/// `{now}`, `{mtime}`, `{ctime}` and `{exif}` are that time and the
+/// modification, creation and EXIF dates of the file at `path`. They are
+/// written as `YYYY-MM-DD` unless a strftime format follows a colon, as in
+/// `{exif:%Y%m%d_%H%M%S}`.
+pub fn apply_date_generators(
+    replace_pattern: &str,
+    now: NaiveDateTime,
+    path: &Path,
+) -> Result<String, String> {
+    lazy_static! {
+        static ref DATE_REGEX: Regex = Regex::new(
+            r"\{(date|year|month|monthname|monthsimp|day|dayname|daysimp|now|mtime|ctime|exif)(?::([^{}]*))?\}"
+        )
+        .unwrap();
+    }
+
+    let mut result = String::new();
+    let mut last = 0;
+    for captures in DATE_REGEX.captures_iter(replace_pattern) {
+        let generator = captures.get(0).unwrap();
+        let name = &captures[1];
+        let format = captures.get(2).map(|format| format.as_str());
+        let (datetime, format) = match (name, format) {
+            ("now", format) => (Some(now), format),
+            ("mtime", format) => (crate::ocd::timestamp::modified(path), format),
+            ("ctime", format) => (crate::ocd::timestamp::created(path), format),
+            ("exif", format) => (crate::ocd::timestamp::exif(path), format),
+            (_, Some(_)) => {
+                return Err(format!(
+                    "Pattern error: {{{}}} does not take a format",
+                    name
+                ))
+            }
+            (_, None) => (Some(now), Some(legacy_format(name))),
+        };
+        let datetime =
+            datetime.ok_or_else(|| format!("Pattern error: {:?} has no {} date", path, name))?;
+        result.push_str(&replace_pattern[last..generator.start()]);
+        result.push_str(&format_date(
+            datetime,
+            format.unwrap_or(DEFAULT_DATE_FORMAT),
+        )?);
+        last = generator.end();
+    }
+    result.push_str(&replace_pattern[last..]);
+    Ok(result)
+}
+
+lazy_static! {
+    static ref RAND_REGEX: Regex = Regex::new(r"\{rand(?:(\d+)(?:-(\d+))?)?(?:,(\d+))?\}").unwrap();
+}
+
+pub fn has_random_generators(replace_pattern: &str) -> bool {
+    RAND_REGEX.is_match(replace_pattern)
+}
+
+/// Replaces the random number generators in a replace pattern.
+///
+/// `{rand}` is a number between 0 and 100, `{rand500}` between 0 and 500 and
+/// `{rand10-20}` between 10 and 20, all inclusive. A width after a comma pads
+/// the number with zeros, as in `{rand20,5}` or `{rand10-20,3}`.
+pub fn apply_random_generators<R: Rng>(
+    replace_pattern: &str,
+    rng: &mut R,
+) -> Result<String, String> {
+    let mut result = String::new();
+    let mut last = 0;
+    for captures in RAND_REGEX.captures_iter(replace_pattern) {
+        let generator = captures.get(0).unwrap();
+        let number = |index: usize| {
+            captures.get(index).map_or(Ok(None), |number| {
+                number.as_str().parse::<u64>().map(Some).map_err(|_| {
+                    format!(
+                        "Pattern error: {} is too large in {}",
+                        number.as_str(),
+                        generator.as_str()
+                    )
+                })
+            })
+        };
+        let (low, high) = match (number(1)?, number(2)?) {
+            (None, _) => (0, 100),
+            (Some(high), None) => (0, high),
+            (Some(low), Some(high)) => (low, high),
+        };
+        if low > high {
+            return Err(format!(
+                "Pattern error: empty range in {}",
+                generator.as_str()
+            ));
+        }
+        let width = number(3)?.unwrap_or(0);
+        if width > MAX_RANDOM_WIDTH as u64 {
+            return Err(format!(
+                "Pattern error: the width in {} is larger than {}",
+                generator.as_str(),
+                MAX_RANDOM_WIDTH
+            ));
+        }
+        result.push_str(&replace_pattern[last..generator.start()]);
+        result.push_str(&format!(
+            "{:0width$}",
+            rng.gen_range(low..=high),
+            width = width as usize
+        ));
+        last = generator.end();
+    }
+    result.push_str(&replace_pattern[last..]);
+    Ok(result)
+}
+
+fn legacy_format(name: &str) -> &'static str {
+    match name {
+        "year" => "%Y",
+        "month" => "%m",
+        "monthname" => "%B",
+        "monthsimp" => "%b",
+        "day" => "%d",
+        "dayname" => "%A",
+        "daysimp" => "%a",
+        _ => DEFAULT_DATE_FORMAT,
+    }
+}
+
+fn format_date(datetime: NaiveDateTime, format: &str) -> Result<String, String> {
+    let items: Vec<Item> = StrftimeItems::new(format).collect();
+    if items.iter().any(|item| matches!(item, Item::Error)) {
+        return Err(format!("Pattern error: invalid date format {:?}", format));
+    }
+    let formatted = datetime.format_with_items(items.into_iter()).to_string();
+    if formatted.contains('/') {
+        return Err(format!(
+            "Pattern error: the date format {:?} produces a '/'",
+            format
+        ));
+    }
+    Ok(formatted)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::NaiveDate;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn now() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2019, 10, 21)
+            .unwrap()
+            .and_hms_opt(8, 5, 9)
+            .unwrap()
+    }
+
+    #[test]
+    fn current_date_test() {
+        let path = Path::new("missing.jpg");
+        assert_eq!(
+            apply_date_generators("{date} {1}", now(), path).unwrap(),
+            "2019-10-21 {1}"
+        );
+        assert_eq!(
+            apply_date_generators(
+                "{year}{month}{day} {monthname} {monthsimp} {dayname} {daysimp}",
+                now(),
+                path
+            )
+            .unwrap(),
+            "20191021 October Oct Monday Mon"
+        );
+        assert_eq!(
+            apply_date_generators("{now:%H.%M.%S}", now(), path).unwrap(),
+            "08.05.09"
+        );
+    }
+
+    #[test]
+    fn file_date_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, "").unwrap();
+        let modified = crate::ocd::timestamp::modified(&path).unwrap();
+        assert_eq!(
+            apply_date_generators("{mtime:%Y%m%d_%H%M%S} {1}", now(), &path).unwrap(),
+            format!("{} {{1}}", modified.format("%Y%m%d_%H%M%S"))
+        );
+        assert!(apply_date_generators("{exif}", now(), &path).is_err());
+    }
+
+    #[test]
+    fn invalid_format_test() {
+        let path = Path::new("missing.jpg");
+        assert!(apply_date_generators("{year:%Y}", now(), path).is_err());
+        assert!(apply_date_generators("{now:%Q}", now(), path).is_err());
+        assert!(apply_date_generators("{now:%D}", now(), path).is_err());
+    }
+
+    #[test]
+    fn random_test() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..100 {
+            let number: u64 = apply_random_generators("{rand}", &mut rng)
+                .unwrap()
+                .parse()
+                .unwrap();
+            assert!(number <= 100);
+            let number: u64 = apply_random_generators("{rand5-7}", &mut rng)
+                .unwrap()
+                .parse()
+                .unwrap();
+            assert!((5..=7).contains(&number));
+        }
+        assert_eq!(
+            apply_random_generators("{rand3-3,4} {rand0}", &mut rng).unwrap(),
+            "0003 0"
+        );
+        assert_eq!(apply_random_generators("{1}", &mut rng).unwrap(), "{1}");
+        assert!(apply_random_generators("{rand7-5}", &mut rng).is_err());
+        assert!(apply_random_generators("{rand1,99999999999}", &mut rng).is_err());
+        assert_eq!(
+            apply_random_generators("{rand0,20}", &mut rng),
+            Ok("0".repeat(20))
+        );
+    }
+
+    #[test]
+    fn random_seed_test() {
+        let generate = |seed| {
+            apply_random_generators("{rand1000,4}", &mut StdRng::seed_from_u64(seed)).unwrap()
+        };
+        assert_eq!(generate(7), generate(7));
+        assert!(has_random_generators("{rand,2}"));
+        assert!(!has_random_generators("{random}"));
+    }
+}
@@ -0,0 +1,168 @@
+use crate::ocd::error::OcdError;
+use crate::ocd::input::Choice;
+use crate::ocd::mrn::words::{shape, split_words};
+use crate::ocd::mrn::{pattern_match_regex, Rule};
+use std::path::PathBuf;
+
+/// The separators offered to join the tokens picked with `it`.
+const SEPARATORS: [(&str, &str); 6] = [
+    ("space", " "),
+    ("dash", "-"),
+    ("spaced dash", " - "),
+    ("underscore", "_"),
+    ("period", "."),
+    ("none", ""),
+];
+
+/// The names of the files before and after a rule is applied.
+pub type Preview = Vec<(PathBuf, PathBuf)>;
+
+/// Builds the rule for `ip`: asks for a match and a replace pattern, previews
+/// the new names and lets the user accept the rule, edit the patterns or skip
+/// the rule altogether.
+pub fn pattern_match<F>(preview: F) -> Result<Option<Rule>, OcdError>
+where
+    F: Fn(&Rule) -> Result<Preview, OcdError>,
+{
+    let mut pattern = String::from("{X}");
+    let mut replace = String::from("{1}");
+    loop {
+        pattern = text("Match pattern", &pattern)?;
+        if let Err(reason) = pattern_match_regex(&pattern) {
+            crate::ocd::output::mrn_invalid_input(&format!("Invalid match pattern: {}", reason));
+            continue;
+        }
+        replace = text("Replace pattern", &replace)?;
+
+        let rule = Rule::PatternMatch {
+            pattern: pattern.clone(),
+            replace: replace.clone(),
+        };
+        match preview(&rule) {
+            Ok(names) => crate::ocd::output::mrn_preview(&names),
+            Err(reason) => {
+                crate::ocd::output::mrn_invalid_input(&reason.to_string());
+                continue;
+            }
+        }
+        match choice()? {
+            Choice::Accept => return Ok(Some(rule)),
+            Choice::Edit => continue,
+            Choice::Skip => return Ok(None),
+        }
+    }
+}
+
+/// Builds the rule for `it`: splits the name of the sample file, without its
+/// extension, into tokens, asks which of them to keep and in what order, and
+/// which separator to join them with. The resulting recipe applies to every
+/// file whose name splits into tokens of the same shape as the sample.
+pub fn tokenize<F>(name: &str, preview: F) -> Result<Option<Rule>, OcdError>
+where
+    F: Fn(&Rule) -> Result<Preview, OcdError>,
+{
+    let tokens = split_words(name);
+    if tokens.is_empty() {
+        crate::ocd::output::mrn_invalid_input(&format!("No tokens found in {:?}", name));
+        return Ok(None);
+    }
+
+    let mut order_input = (1..=tokens.len())
+        .map(|index| index.to_string())
+        .collect::<Vec<String>>()
+        .join(" ");
+    let mut separator = 0;
+    loop {
+        crate::ocd::output::mrn_tokens(&tokens);
+        order_input = text("Tokens to keep, in order", &order_input)?;
+        let (order, rest) = match token_order(&order_input, tokens.len()) {
+            Ok(order) => order,
+            Err(reason) => {
+                crate::ocd::output::mrn_invalid_input(&reason);
+                continue;
+            }
+        };
+        let names: Vec<&str> = SEPARATORS.iter().map(|(name, _)| *name).collect();
+        separator = crate::ocd::input::user_select("Separator", &names, separator)
+            .map_err(OcdError::Terminal)?;
+
+        let rule = Rule::Tokenize {
+            shape: shape(&tokens),
+            order,
+            separator: String::from(SEPARATORS[separator].1),
+            rest,
+        };
+        match preview(&rule) {
+            Ok(names) => crate::ocd::output::mrn_preview(&names),
+            Err(reason) => {
+                crate::ocd::output::mrn_invalid_input(&reason.to_string());
+                continue;
+            }
+        }
+        match choice()? {
+            Choice::Accept => return Ok(Some(rule)),
+            Choice::Edit => continue,
+            Choice::Skip => return Ok(None),
+        }
+    }
+}
+
+/// Parses the one-based token numbers typed by the user, separated by spaces
+/// or commas, into indices. The last token can be followed by `+` to take the
+/// tokens after it in longer names, which is returned as the second value.
+fn token_order(input: &str, count: usize) -> Result<(Vec<usize>, bool), String> {
+    let mut rest = false;
+    let order = input
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|number| !number.is_empty())
+        .map(|token| {
+            let (number, plus) = match token.strip_suffix('+') {
+                Some(number) => (number, true),
+                None => (token, false),
+            };
+            match number.parse::<usize>() {
+                Ok(number) if plus && number != count => Err(format!(
+                    "Invalid token {:?}, only the last token, {}, can take the ones after it",
+                    token, count
+                )),
+                Ok(number) if number >= 1 && number <= count => {
+                    rest |= plus;
+                    Ok(number - 1)
+                }
+                _ => Err(format!(
+                    "Invalid token {:?}, expected a number between 1 and {}",
+                    token, count
+                )),
+            }
+        })
+        .collect::<Result<Vec<usize>, String>>()?;
+    if order.is_empty() {
+        return Err(String::from("Keep at least one token"));
+    }
+    Ok((order, rest))
+}
+
+fn text(prompt: &str, default: &str) -> Result<String, OcdError> {
+    crate::ocd::input::user_text(prompt, default).map_err(OcdError::Terminal)
+}
+
+fn choice() -> Result<Choice, OcdError> {
+    crate::ocd::input::user_choice().map_err(OcdError::Terminal)
+}
+
+#[cfg(test)]
+mod test {
+    use super::token_order;
+
+    #[test]
+    fn token_order_test() {
+        assert_eq!(token_order("2 1", 2), Ok((vec![1, 0], false)));
+        assert_eq!(token_order(" 3,1, 1 ", 3), Ok((vec![2, 0, 0], false)));
+        assert_eq!(token_order("2+ 1", 2), Ok((vec![1, 0], true)));
+        assert!(token_order("1+ 2", 2).is_err());
+        assert!(token_order("0", 3).is_err());
+        assert!(token_order("4", 3).is_err());
+        assert!(token_order("a", 3).is_err());
+        assert!(token_order(" ", 3).is_err());
+    }
+}
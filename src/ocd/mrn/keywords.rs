@@ -0,0 +1,627 @@
+use crate::ocd::mrn::cleanup::{Class, CLASSES};
+use crate::ocd::mrn::diagnostic::did_you_mean;
+use crate::ocd::mrn::lexer::Token;
+use crate::ocd::mrn::normalization::Form;
+use crate::ocd::mrn::sanitize::{Profile, PROFILES};
+use crate::ocd::mrn::{Position, Rule, RuleRegex};
+use regex::Regex;
+
+/// What an argument of a rule is written as.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Kind {
+    /// A quoted string.
+    String,
+    /// An index from the start or, if negative, the end, the `end` keyword,
+    /// or `before` or `after` an anchor.
+    Position,
+}
+
+impl Kind {
+    pub fn description(self) -> &'static str {
+        match self {
+            Kind::String => "a string",
+            Kind::Position => "a position",
+        }
+    }
+}
+
+/// An argument of a rule, named for the help text and the error messages.
+pub struct Argument {
+    pub name: &'static str,
+    pub kind: Kind,
+}
+
+/// The value read for an argument.
+#[derive(Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Position(Position),
+}
+
+/// The values read for the arguments of a rule, taken in order by the
+/// function that builds the rule. The parser has already checked them against
+/// the signature, so taking one of the wrong kind is a bug.
+pub struct Arguments {
+    values: std::vec::IntoIter<Value>,
+    /// How many values have been taken, so that an error building the rule
+    /// can point at the last one.
+    pub taken: usize,
+}
+
+impl Arguments {
+    pub fn new(values: Vec<Value>) -> Arguments {
+        Arguments {
+            values: values.into_iter(),
+            taken: 0,
+        }
+    }
+
+    fn next(&mut self) -> Option<Value> {
+        self.taken += 1;
+        self.values.next()
+    }
+
+    pub fn string(&mut self) -> String {
+        match self.next() {
+            Some(Value::String(value)) => value,
+            value => unreachable!("expected a string argument, found {:?}", value),
+        }
+    }
+
+    pub fn position(&mut self) -> Position {
+        match self.next() {
+            Some(Value::Position(value)) => value,
+            value => unreachable!("expected a position argument, found {:?}", value),
+        }
+    }
+}
+
+/// A rule as it is written in the rules: the keyword, the token the lexer
+/// reads it as, the arguments that follow it, separated by spaces, and how the
+/// parser builds the rule from their values.
+///
+/// Adding a rule only takes a new entry in `KEYWORDS`, besides its token and
+/// the rule itself.
+pub struct Keyword {
+    pub name: &'static str,
+    pub token: Token,
+    pub arguments: &'static [Argument],
+    pub help: &'static str,
+    pub rule: fn(&mut Arguments) -> Result<Rule, String>,
+}
+
+impl Keyword {
+    /// How the rule is written, as in `i "<text>" <position>`.
+    pub fn usage(&self) -> String {
+        let mut usage = String::from(self.name);
+        for argument in self.arguments {
+            match argument.kind {
+                Kind::String => usage.push_str(&format!(" \"<{}>\"", argument.name)),
+                Kind::Position => usage.push_str(&format!(" <{}>", argument.name)),
+            }
+        }
+        usage
+    }
+}
+
+const TEXT: Argument = Argument {
+    name: "text",
+    kind: Kind::String,
+};
+const MATCH: Argument = Argument {
+    name: "match",
+    kind: Kind::String,
+};
+
+/// Every rule, in the order they are listed in the help.
+pub static KEYWORDS: [Keyword; 45] = [
+    Keyword {
+        name: "lc",
+        token: Token::LowerCase,
+        arguments: &[],
+        help: "Lower case",
+        rule: |_| Ok(Rule::LowerCase),
+    },
+    Keyword {
+        name: "uc",
+        token: Token::UpperCase,
+        arguments: &[],
+        help: "Upper case",
+        rule: |_| Ok(Rule::UpperCase),
+    },
+    Keyword {
+        name: "tc",
+        token: Token::TitleCase,
+        arguments: &[],
+        help: "Title case",
+        rule: |_| Ok(Rule::TitleCase),
+    },
+    Keyword {
+        name: "sc",
+        token: Token::SentenceCase,
+        arguments: &[],
+        help: "Sentence case",
+        rule: |_| Ok(Rule::SentenceCase),
+    },
+    Keyword {
+        name: "ccj",
+        token: Token::CamelCaseJoin,
+        arguments: &[],
+        help: "Camel case join",
+        rule: |_| Ok(Rule::CamelCaseJoin),
+    },
+    Keyword {
+        name: "ccs",
+        token: Token::CamelCaseSplit,
+        arguments: &[],
+        help: "Camel case split",
+        rule: |_| Ok(Rule::CamelCaseSplit),
+    },
+    Keyword {
+        name: "snc",
+        token: Token::SnakeCase,
+        arguments: &[],
+        help: "Snake case (snake_case)",
+        rule: |_| Ok(Rule::SnakeCase),
+    },
+    Keyword {
+        name: "kc",
+        token: Token::KebabCase,
+        arguments: &[],
+        help: "Kebab case (kebab-case)",
+        rule: |_| Ok(Rule::KebabCase),
+    },
+    Keyword {
+        name: "ssc",
+        token: Token::ScreamingSnakeCase,
+        arguments: &[],
+        help: "Screaming snake case (SCREAMING_SNAKE)",
+        rule: |_| Ok(Rule::ScreamingSnakeCase),
+    },
+    Keyword {
+        name: "pc",
+        token: Token::PascalCase,
+        arguments: &[],
+        help: "Pascal case (PascalCase)",
+        rule: |_| Ok(Rule::PascalCase),
+    },
+    Keyword {
+        name: "cc",
+        token: Token::CamelCase,
+        arguments: &[],
+        help: "Camel case (camelCase)",
+        rule: |_| Ok(Rule::CamelCase),
+    },
+    Keyword {
+        name: "dc",
+        token: Token::DotCase,
+        arguments: &[],
+        help: "Dot case (dot.case)",
+        rule: |_| Ok(Rule::DotCase),
+    },
+    Keyword {
+        name: "i",
+        token: Token::Insert,
+        arguments: &[
+            TEXT,
+            Argument {
+                name: "position",
+                kind: Kind::Position,
+            },
+        ],
+        help: "Insert",
+        rule: |arguments| {
+            Ok(Rule::Insert {
+                text: arguments.string(),
+                position: arguments.position(),
+            })
+        },
+    },
+    Keyword {
+        name: "d",
+        token: Token::Delete,
+        arguments: &[
+            Argument {
+                name: "from",
+                kind: Kind::Position,
+            },
+            Argument {
+                name: "to",
+                kind: Kind::Position,
+            },
+        ],
+        help: "Delete",
+        rule: |arguments| {
+            let from = arguments.position();
+            let to = arguments.position();
+            // The positions of other kinds depend on the name.
+            let backwards = match (&from, &to) {
+                (Position::Index { value: from }, Position::Index { value: to }) => from > to,
+                (Position::Index { value }, Position::Start) => *value > 0,
+                (Position::FromEnd { value: from }, Position::FromEnd { value: to }) => from < to,
+                (Position::End, Position::Start | Position::Index { .. }) => true,
+                (Position::End, Position::FromEnd { value }) => *value > 0,
+                _ => false,
+            };
+            if backwards {
+                return Err(String::from("Syntax error: d expected <to> after <from>"));
+            }
+            Ok(Rule::Delete { from, to })
+        },
+    },
+    Keyword {
+        name: "s",
+        token: Token::Sanitize,
+        arguments: &[],
+        help: "Sanitize",
+        rule: |_| Ok(Rule::Sanitize),
+    },
+    Keyword {
+        name: "sa",
+        token: Token::SanitizeProfile,
+        arguments: &[Argument {
+            name: "profile",
+            kind: Kind::String,
+        }],
+        help: "Sanitize as windows, slug or ascii",
+        rule: |arguments| {
+            let name = arguments.string();
+            let profile = Profile::find(&name).ok_or_else(|| {
+                let names: Vec<&str> = PROFILES.iter().map(|(name, _)| *name).collect();
+                let hint = did_you_mean(&name, &names)
+                    .unwrap_or_else(|| format!("expected one of {}", names.join(", ")));
+                format!(
+                    "Syntax error: unknown sanitize profile {:?}, {}",
+                    name, hint
+                )
+            })?;
+            Ok(Rule::SanitizeProfile { profile })
+        },
+    },
+    Keyword {
+        name: "nfc",
+        token: Token::NormalizeNfc,
+        arguments: &[],
+        help: "Normalize to composed Unicode (NFC), extension included",
+        rule: |_| Ok(Rule::Normalize { form: Form::Nfc }),
+    },
+    Keyword {
+        name: "nfd",
+        token: Token::NormalizeNfd,
+        arguments: &[],
+        help: "Normalize to decomposed Unicode (NFD), extension included",
+        rule: |_| Ok(Rule::Normalize { form: Form::Nfd }),
+    },
+    Keyword {
+        name: "r",
+        token: Token::Replace,
+        arguments: &[MATCH, TEXT],
+        help: "Replace",
+        rule: |arguments| {
+            Ok(Rule::Replace {
+                pattern: arguments.string(),
+                replace: arguments.string(),
+            })
+        },
+    },
+    Keyword {
+        name: "x",
+        token: Token::Regex,
+        arguments: &[
+            Argument {
+                name: "regex",
+                kind: Kind::String,
+            },
+            TEXT,
+        ],
+        help: "Regex replace, $1 or ${name} insert captures",
+        rule: |arguments| regex(arguments, false),
+    },
+    Keyword {
+        name: "xi",
+        token: Token::RegexCaseInsensitive,
+        arguments: &[
+            Argument {
+                name: "regex",
+                kind: Kind::String,
+            },
+            TEXT,
+        ],
+        help: "Case-insensitive regex replace",
+        rule: |arguments| regex(arguments, true),
+    },
+    Keyword {
+        name: "sd",
+        token: Token::ReplaceSpaceDash,
+        arguments: &[],
+        help: "Substitute space dash",
+        rule: |_| Ok(Rule::ReplaceSpaceDash),
+    },
+    Keyword {
+        name: "sp",
+        token: Token::ReplaceSpacePeriod,
+        arguments: &[],
+        help: "Substitute space period",
+        rule: |_| Ok(Rule::ReplaceSpacePeriod),
+    },
+    Keyword {
+        name: "su",
+        token: Token::ReplaceSpaceUnder,
+        arguments: &[],
+        help: "Substitute space underscore",
+        rule: |_| Ok(Rule::ReplaceSpaceUnder),
+    },
+    Keyword {
+        name: "dp",
+        token: Token::ReplaceDashPeriod,
+        arguments: &[],
+        help: "Substitute dash period",
+        rule: |_| Ok(Rule::ReplaceDashPeriod),
+    },
+    Keyword {
+        name: "ds",
+        token: Token::ReplaceDashSpace,
+        arguments: &[],
+        help: "Substitute dash space",
+        rule: |_| Ok(Rule::ReplaceDashSpace),
+    },
+    Keyword {
+        name: "du",
+        token: Token::ReplaceDashUnder,
+        arguments: &[],
+        help: "Substitute dash underscore",
+        rule: |_| Ok(Rule::ReplaceDashUnder),
+    },
+    Keyword {
+        name: "pd",
+        token: Token::ReplacePeriodDash,
+        arguments: &[],
+        help: "Substitute period dash",
+        rule: |_| Ok(Rule::ReplacePeriodDash),
+    },
+    Keyword {
+        name: "ps",
+        token: Token::ReplacePeriodSpace,
+        arguments: &[],
+        help: "Substitute period space",
+        rule: |_| Ok(Rule::ReplacePeriodSpace),
+    },
+    Keyword {
+        name: "pu",
+        token: Token::ReplacePeriodUnder,
+        arguments: &[],
+        help: "Substitute period under",
+        rule: |_| Ok(Rule::ReplacePeriodUnder),
+    },
+    Keyword {
+        name: "ud",
+        token: Token::ReplaceUnderDash,
+        arguments: &[],
+        help: "Substitute underscore dash",
+        rule: |_| Ok(Rule::ReplaceUnderDash),
+    },
+    Keyword {
+        name: "up",
+        token: Token::ReplaceUnderPeriod,
+        arguments: &[],
+        help: "Substitute underscore period",
+        rule: |_| Ok(Rule::ReplaceUnderPeriod),
+    },
+    Keyword {
+        name: "us",
+        token: Token::ReplaceUnderSpace,
+        arguments: &[],
+        help: "Substitute underscore space",
+        rule: |_| Ok(Rule::ReplaceUnderSpace),
+    },
+    Keyword {
+        name: "tw",
+        token: Token::TrimWhitespace,
+        arguments: &[],
+        help: "Trim whitespace at the start and end",
+        rule: |_| {
+            Ok(Rule::Trim {
+                class: Class::Space,
+            })
+        },
+    },
+    Keyword {
+        name: "tp",
+        token: Token::TrimPunctuation,
+        arguments: &[],
+        help: "Trim punctuation at the start and end, except brackets",
+        rule: |_| {
+            Ok(Rule::Trim {
+                class: Class::Punctuation,
+            })
+        },
+    },
+    Keyword {
+        name: "cs",
+        token: Token::Collapse,
+        arguments: &[Argument {
+            name: "class",
+            kind: Kind::String,
+        }],
+        help: "Collapse repeats of space, dash, period, under, separator or punctuation",
+        rule: |arguments| {
+            let name = arguments.string();
+            let class = Class::find(&name).ok_or_else(|| {
+                let names: Vec<&str> = CLASSES.iter().map(|(name, _)| *name).collect();
+                let hint = did_you_mean(&name, &names)
+                    .unwrap_or_else(|| format!("expected one of {}", names.join(", ")));
+                format!("Syntax error: unknown character class {:?}, {}", name, hint)
+            })?;
+            Ok(Rule::Collapse { class })
+        },
+    },
+    Keyword {
+        name: "ea",
+        token: Token::ExtensionAdd,
+        arguments: &[Argument {
+            name: "extension",
+            kind: Kind::String,
+        }],
+        help: "Extension add",
+        rule: |arguments| {
+            Ok(Rule::ExtensionAdd {
+                extension: arguments.string(),
+            })
+        },
+    },
+    Keyword {
+        name: "er",
+        token: Token::ExtensionRemove,
+        arguments: &[],
+        help: "Extension remove",
+        rule: |_| Ok(Rule::ExtensionRemove),
+    },
+    Keyword {
+        name: "el",
+        token: Token::ExtensionLowerCase,
+        arguments: &[],
+        help: "Extension lower case",
+        rule: |_| Ok(Rule::ExtensionLowerCase),
+    },
+    Keyword {
+        name: "eu",
+        token: Token::ExtensionUpperCase,
+        arguments: &[],
+        help: "Extension upper case",
+        rule: |_| Ok(Rule::ExtensionUpperCase),
+    },
+    Keyword {
+        name: "em",
+        token: Token::ExtensionMap,
+        arguments: &[
+            Argument {
+                name: "from",
+                kind: Kind::String,
+            },
+            Argument {
+                name: "to",
+                kind: Kind::String,
+            },
+        ],
+        help: "Extension map, ignoring case (e.g. em \"jpeg\" \"jpg\")",
+        rule: |arguments| {
+            Ok(Rule::ExtensionMap {
+                from: arguments.string(),
+                to: arguments.string(),
+            })
+        },
+    },
+    Keyword {
+        name: "ed",
+        token: Token::ExtensionDetect,
+        arguments: &[],
+        help: "Extension detect, from the contents if missing or wrong",
+        rule: |_| Ok(Rule::ExtensionDetect),
+    },
+    Keyword {
+        name: "p",
+        token: Token::PatternMatch,
+        arguments: &[
+            MATCH,
+            Argument {
+                name: "pattern",
+                kind: Kind::String,
+            },
+        ],
+        help: "Pattern match",
+        rule: |arguments| {
+            Ok(Rule::PatternMatch {
+                pattern: arguments.string(),
+                replace: arguments.string(),
+            })
+        },
+    },
+    Keyword {
+        name: "ip",
+        token: Token::InteractivePatternMatch,
+        arguments: &[],
+        help: "Interactive pattern match",
+        rule: |_| Ok(Rule::InteractivePatternMatch),
+    },
+    Keyword {
+        name: "it",
+        token: Token::InteractiveTokenize,
+        arguments: &[],
+        help: "Interactive tokenize",
+        rule: |_| Ok(Rule::InteractiveTokenize),
+    },
+];
+
+/// The keyword written as `name`.
+pub fn find(name: &str) -> Option<&'static Keyword> {
+    KEYWORDS.iter().find(|keyword| keyword.name == name)
+}
+
+/// The keyword the lexer reads as `token`, if the token is a rule.
+pub fn of(token: &Token) -> Option<&'static Keyword> {
+    KEYWORDS.iter().find(|keyword| keyword.token == *token)
+}
+
+/// The list of rules in the help of `mrn`, one per line.
+pub fn help() -> String {
+    let usages: Vec<String> = KEYWORDS.iter().map(Keyword::usage).collect();
+    let width = usages.iter().map(String::len).max().unwrap_or(0) + 3;
+    KEYWORDS
+        .iter()
+        .zip(usages)
+        .map(|(keyword, usage)| format!("{:<width$}{}", usage, keyword.help, width = width))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn regex(arguments: &mut Arguments, case_insensitive: bool) -> Result<Rule, String> {
+    let pattern = arguments.string();
+    // The flag is written into the expression so that it is part of the rule
+    // when rules are compared.
+    let expression = if case_insensitive {
+        format!("(?i){}", pattern)
+    } else {
+        pattern.clone()
+    };
+    let regex = Regex::new(&expression)
+        .map_err(|reason| format!("Syntax error: invalid regex {:?}\n{}", pattern, reason))?;
+    Ok(Rule::Regex {
+        regex: RuleRegex(regex),
+        replace: arguments.string(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unique_test() {
+        for (i, keyword) in KEYWORDS.iter().enumerate() {
+            assert!(
+                KEYWORDS[i + 1..]
+                    .iter()
+                    .all(|other| other.name != keyword.name && other.token != keyword.token),
+                "{} is declared twice",
+                keyword.name
+            );
+        }
+    }
+
+    #[test]
+    fn usage_test() {
+        assert_eq!(find("i").unwrap().usage(), "i \"<text>\" <position>");
+        assert_eq!(of(&Token::LowerCase).unwrap().usage(), "lc");
+        assert!(of(&Token::Comma).is_none());
+        let help = help();
+        let lines: Vec<&str> = help.lines().collect();
+        assert_eq!(lines.len(), KEYWORDS.len());
+        assert!(lines[0].starts_with("lc "));
+        assert!(lines[0].ends_with(" Lower case"));
+        // The descriptions are aligned.
+        let column = lines[0].find("Lower").unwrap();
+        assert!(lines
+            .iter()
+            .all(|line| line[column - 1..].starts_with(' ') && !line[column..].starts_with(' ')));
+    }
+}
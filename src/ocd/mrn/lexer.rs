@@ -1,15 +1,31 @@
+use crate::ocd::mrn::conditions;
+use crate::ocd::mrn::diagnostic::{did_you_mean, Diagnostic};
+use crate::ocd::mrn::keywords::{self, KEYWORDS};
 use crate::ocd::mrn::MassRenameConfig;
+use std::error::Error;
 use std::fmt;
 use std::fmt::Display;
-use std::{error::Error, mem};
 use tracing::{span, Level};
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Token {
     Comma,
     Space,
+    OpenBrace,
+    CloseBrace,
+    Not,
+    If,
+    Else,
     End,
+    Condition { value: String },
+    RegexLiteral { value: String },
     Number { value: usize },
+    NegativeNumber { value: usize },
+    Start,
+    Before,
+    After,
+    Digit,
+    Letter,
     String { value: String },
     PatternMatch,
     LowerCase,
@@ -18,8 +34,18 @@ pub enum Token {
     SentenceCase,
     CamelCaseJoin,
     CamelCaseSplit,
+    SnakeCase,
+    KebabCase,
+    ScreamingSnakeCase,
+    PascalCase,
+    CamelCase,
+    DotCase,
     ExtensionAdd,
     ExtensionRemove,
+    ExtensionLowerCase,
+    ExtensionUpperCase,
+    ExtensionMap,
+    ExtensionDetect,
     Insert,
     InteractiveTokenize,
     InteractivePatternMatch,
@@ -37,24 +63,64 @@ pub enum Token {
     ReplaceUnderSpace,
     ReplaceUnderDash,
     ReplaceUnderPeriod,
+    TrimWhitespace,
+    TrimPunctuation,
+    Collapse,
+    Regex,
+    RegexCaseInsensitive,
     Sanitize,
+    SanitizeProfile,
+    NormalizeNfc,
+    NormalizeNfd,
 }
 
+/// A token and where it was read from, in characters from the start of the
+/// rules.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lexeme {
+    pub token: Token,
+    pub offset: usize,
+    pub length: usize,
+}
+
+/// The tokens read from the rules, along with the rules themselves so that
+/// the parser can point at the offending part when reporting errors.
 #[derive(Debug)]
+pub struct Tokens {
+    pub input: String,
+    pub lexemes: Vec<Lexeme>,
+}
+
+/// The word written in place of an index to mean the end of the name.
+const END: &str = "end";
+
+const IF: &str = "if";
+
+const ELSE: &str = "else";
+
+/// The words that make up positions other than indices, besides `END`.
+const POSITIONS: [(&str, Token); 5] = [
+    ("start", Token::Start),
+    ("before", Token::Before),
+    ("after", Token::After),
+    ("digit", Token::Digit),
+    ("letter", Token::Letter),
+];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum TokenizerErrorKind {
     Unexpected,
     UnfinishedString,
+    UnfinishedRegex,
     UnfinishedRule,
     ParseIntError,
 }
 
 #[derive(Debug)]
 pub struct TokenizerError {
-    kind: TokenizerErrorKind,
-    // input: String,
-    state: TokenizerState,
-    // position: usize,
-    msg: String,
+    pub kind: TokenizerErrorKind,
+    pub msg: String,
+    pub diagnostic: Diagnostic,
 }
 
 impl Error for TokenizerError {
@@ -65,909 +131,270 @@ impl Error for TokenizerError {
 
 impl Display for TokenizerError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "TokenizerError")
+        write!(f, "{}\n{}", self.msg, self.diagnostic)
     }
 }
 
-#[derive(Debug)]
-#[allow(clippy::upper_case_acronyms)]
-enum TokenizerState {
-    Init,
-    Error,
-    Comma,
-    Space,
-    String,
-    Number,
-    C,
-    CC,
-    CCJ,
-    CCS,
-    DP,
-    DS,
-    DU,
-    D,
-    E,
-    EN,
-    END,
-    EA,
-    ER,
-    I,
-    IP,
-    IT,
-    L,
-    LC,
-    P,
-    PD,
-    PS,
-    PU,
-    R,
-    S,
-    SC,
-    SP,
-    SD,
-    SU,
-    T,
-    TC,
-    U,
-    UC,
-    UD,
-    US,
-    UP,
+/// Whether `c` ends a word.
+fn is_separator(c: char) -> bool {
+    matches!(c, ',' | ' ' | '"' | '{' | '}')
 }
 
-struct Tokenizer {
-    state: TokenizerState,
-    string: String,
-    number: String,
-    tokens: Vec<Token>,
+/// Splits the rules into commas, braces, runs of spaces, quoted strings,
+/// regexes between slashes and words. Words are numbers, conditions, the end,
+/// if and else keywords or one of the keywords in `KEYWORDS`.
+struct Tokenizer<'a> {
+    input: &'a str,
+    chars: Vec<char>,
+    /// Where the character being read is.
+    position: usize,
+    lexemes: Vec<Lexeme>,
 }
 
-impl Tokenizer {
-    pub fn new() -> Tokenizer {
+impl<'a> Tokenizer<'a> {
+    fn new(input: &'a str) -> Tokenizer<'a> {
         Tokenizer {
-            state: TokenizerState::Init,
-            string: String::new(),
-            number: String::new(),
-            tokens: Vec::new(),
+            input,
+            chars: input.chars().collect(),
+            position: 0,
+            lexemes: Vec::new(),
         }
     }
 
-    pub fn run(
-        &mut self,
-        config: &MassRenameConfig,
-        input: &str,
-    ) -> Result<Vec<Token>, Box<dyn Error>> {
+    fn run(mut self) -> Result<Tokens, TokenizerError> {
         let span = span!(Level::TRACE, "lexer");
         let _guard = span.enter();
 
-        for c in input.chars() {
-            match self.state {
-                TokenizerState::Init => self.state_init(config, c),
-                TokenizerState::Comma => self.state_comma(config, c),
-                TokenizerState::Space => self.state_space(config, c),
-                TokenizerState::String => self.state_string(config, c),
-                TokenizerState::Number => self.state_number(config, c),
-                TokenizerState::C => self.state_c(config, c),
-                TokenizerState::CC => self.state_cc(config, c),
-                TokenizerState::CCJ => self.state_ccj(config, c),
-                TokenizerState::CCS => self.state_ccs(config, c),
-                TokenizerState::DP => self.state_dp(config, c),
-                TokenizerState::DS => self.state_ds(config, c),
-                TokenizerState::DU => self.state_du(config, c),
-                TokenizerState::D => self.state_d(config, c),
-                TokenizerState::E => self.state_e(config, c),
-                TokenizerState::EN => self.state_en(config, c),
-                TokenizerState::END => self.state_end(config, c),
-                TokenizerState::EA => self.state_ea(config, c),
-                TokenizerState::ER => self.state_er(config, c),
-                TokenizerState::I => self.state_i(config, c),
-                TokenizerState::IP => self.state_ip(config, c),
-                TokenizerState::IT => self.state_it(config, c),
-                TokenizerState::L => self.state_l(config, c),
-                TokenizerState::LC => self.state_lc(config, c),
-                TokenizerState::P => self.state_p(config, c),
-                TokenizerState::PS => self.state_ps(config, c),
-                TokenizerState::PD => self.state_pd(config, c),
-                TokenizerState::PU => self.state_pu(config, c),
-                TokenizerState::R => self.state_r(config, c),
-                TokenizerState::S => self.state_s(config, c),
-                TokenizerState::SC => self.state_sc(config, c),
-                TokenizerState::SP => self.state_sp(config, c),
-                TokenizerState::SD => self.state_sd(config, c),
-                TokenizerState::SU => self.state_su(config, c),
-                TokenizerState::T => self.state_t(config, c),
-                TokenizerState::TC => self.state_tc(config, c),
-                TokenizerState::U => self.state_u(config, c),
-                TokenizerState::UC => self.state_uc(config, c),
-                TokenizerState::UD => self.state_ud(config, c),
-                TokenizerState::US => self.state_us(config, c),
-                TokenizerState::UP => self.state_up(config, c),
-                TokenizerState::Error => {
-                    return Err(Box::new(TokenizerError {
-                        kind: TokenizerErrorKind::Unexpected,
-                        state: TokenizerState::Error,
-                        msg: String::from("Unexpected lexer error"),
-                    }))
+        while let Some(&c) = self.chars.get(self.position) {
+            let start = self.position;
+            let token = match c {
+                ',' => {
+                    self.position += 1;
+                    Token::Comma
                 }
-            }
-        }
-        match self.state {
-            TokenizerState::Init => {}
-            TokenizerState::Comma => {
-                self.tokens.push(Token::Comma);
-            }
-            TokenizerState::Space => {
-                self.tokens.push(Token::Space);
-            }
-            TokenizerState::Number => match self.number.parse::<usize>() {
-                Ok(value) => {
-                    self.tokens.push(Token::Number { value });
+                ' ' => {
+                    self.skip(|c| c == ' ');
+                    Token::Space
                 }
-                Err(err) => {
-                    return Err(Box::new(TokenizerError {
-                        kind: TokenizerErrorKind::ParseIntError,
-                        state: TokenizerState::Number,
-                        msg: format!("Error: unable to read number: {:?}", err),
-                    }))
+                '{' | '}' | '!' => {
+                    self.position += 1;
+                    match c {
+                        '{' => Token::OpenBrace,
+                        '}' => Token::CloseBrace,
+                        _ => Token::Not,
+                    }
                 }
-            },
-            TokenizerState::CCJ => {
-                self.tokens.push(Token::CamelCaseJoin);
-            }
-            TokenizerState::CCS => {
-                self.tokens.push(Token::CamelCaseSplit);
-            }
-            TokenizerState::D => {
-                self.tokens.push(Token::Delete);
-            }
-            TokenizerState::DP => {
-                self.tokens.push(Token::ReplaceDashPeriod);
-            }
-            TokenizerState::DS => {
-                self.tokens.push(Token::ReplaceDashSpace);
-            }
-            TokenizerState::DU => {
-                self.tokens.push(Token::ReplaceDashUnder);
-            }
-            TokenizerState::EA => {
-                self.tokens.push(Token::ExtensionAdd);
-            }
-            TokenizerState::ER => {
-                self.tokens.push(Token::ExtensionRemove);
-            }
-            TokenizerState::END => {
-                self.tokens.push(Token::End);
-            }
-            TokenizerState::I => {
-                self.tokens.push(Token::Insert);
-            }
-            TokenizerState::IP => {
-                self.tokens.push(Token::InteractivePatternMatch);
-            }
-            TokenizerState::IT => {
-                self.tokens.push(Token::InteractiveTokenize);
-            }
-            TokenizerState::LC => {
-                self.tokens.push(Token::LowerCase);
-            }
-            TokenizerState::P => {
-                self.tokens.push(Token::PatternMatch);
-            }
-            TokenizerState::PD => {
-                self.tokens.push(Token::ReplacePeriodDash);
-            }
-            TokenizerState::PS => {
-                self.tokens.push(Token::ReplacePeriodSpace);
-            }
-            TokenizerState::PU => {
-                self.tokens.push(Token::ReplacePeriodUnder);
-            }
-            TokenizerState::R => {
-                self.tokens.push(Token::Replace);
-            }
-            TokenizerState::S => {
-                self.tokens.push(Token::Sanitize);
-            }
-            TokenizerState::SC => {
-                self.tokens.push(Token::SentenceCase);
-            }
-            TokenizerState::SP => {
-                self.tokens.push(Token::ReplaceSpacePeriod);
-            }
-            TokenizerState::SD => {
-                self.tokens.push(Token::ReplaceSpaceDash);
-            }
-            TokenizerState::SU => {
-                self.tokens.push(Token::ReplaceSpaceUnder);
-            }
-            TokenizerState::TC => {
-                self.tokens.push(Token::TitleCase);
-            }
-            TokenizerState::UC => {
-                self.tokens.push(Token::UpperCase);
-            }
-            TokenizerState::UD => {
-                self.tokens.push(Token::ReplaceUnderDash);
-            }
-            TokenizerState::UP => {
-                self.tokens.push(Token::ReplaceUnderPeriod);
-            }
-            TokenizerState::US => {
-                self.tokens.push(Token::ReplaceUnderSpace);
-            }
-            TokenizerState::String => {
-                return Err(Box::new(TokenizerError {
-                    kind: TokenizerErrorKind::UnfinishedString,
-                    state: TokenizerState::String,
-                    msg: String::from("Error: unfinished string"),
-                }))
-            }
-            TokenizerState::C => {
-                return Err(Box::new(TokenizerError {
-                    kind: TokenizerErrorKind::UnfinishedRule,
-                    state: TokenizerState::C,
-                    msg: String::from("Error: unfinished case rule"),
-                }))
-            }
-            TokenizerState::CC => {
-                return Err(Box::new(TokenizerError {
-                    kind: TokenizerErrorKind::UnfinishedRule,
-                    state: TokenizerState::CC,
-                    msg: String::from("Error: unfinished rule, read: 'cc'"),
-                }))
-            }
-            TokenizerState::E => {
-                return Err(Box::new(TokenizerError {
-                    kind: TokenizerErrorKind::UnfinishedRule,
-                    state: TokenizerState::E,
-                    msg: String::from("Error: unfinished rule, read: 'e'"),
-                }))
-            }
-            TokenizerState::EN => {
-                return Err(Box::new(TokenizerError {
-                    kind: TokenizerErrorKind::UnfinishedRule,
-                    state: TokenizerState::EN,
-                    msg: String::from("Error: unfinished end"),
-                }))
-            }
-            TokenizerState::L => {
-                return Err(Box::new(TokenizerError {
-                    kind: TokenizerErrorKind::UnfinishedRule,
-                    state: TokenizerState::L,
-                    msg: String::from("Error: unfinished rule, read: 'l'"),
-                }))
-            }
-            TokenizerState::T => {
-                return Err(Box::new(TokenizerError {
-                    kind: TokenizerErrorKind::UnfinishedRule,
-                    state: TokenizerState::T,
-                    msg: String::from("Error: unfinished rule, read: 't'"),
-                }))
-            }
-            TokenizerState::U => {
-                return Err(Box::new(TokenizerError {
-                    kind: TokenizerErrorKind::UnfinishedRule,
-                    state: TokenizerState::U,
-                    msg: String::from("Error: unfinished rule, read: 'u'"),
-                }))
-            }
-            TokenizerState::Error => {
-                return Err(Box::new(TokenizerError {
-                    kind: TokenizerErrorKind::UnfinishedRule,
-                    state: TokenizerState::Error,
-                    msg: String::from("Error while reading input"),
-                }))
-            }
-        }
-        let mut tokens = Vec::new();
-        mem::swap(&mut self.tokens, &mut tokens);
-        Ok(tokens)
-    }
-
-    fn state_init(&mut self, config: &MassRenameConfig, c: char) {
-        match c {
-            ',' => {
-                self.state = TokenizerState::Comma;
-            }
-            ' ' => {
-                self.state = TokenizerState::Space;
-            }
-            '"' => {
-                self.string.clear();
-                self.state = TokenizerState::String;
-            }
-            '0' | '1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' | '9' => {
-                self.number.clear();
-                self.number.push(c);
-                self.state = TokenizerState::Number;
-            }
-            'c' => {
-                self.state = TokenizerState::C;
-            }
-            'd' => {
-                self.state = TokenizerState::D;
-            }
-            'e' => {
-                self.state = TokenizerState::E;
-            }
-            'i' => {
-                self.state = TokenizerState::I;
-            }
-            'l' => {
-                self.state = TokenizerState::L;
-            }
-            'p' => {
-                self.state = TokenizerState::P;
-            }
-            'r' => {
-                self.state = TokenizerState::R;
-            }
-            's' => {
-                self.state = TokenizerState::S;
-            }
-            't' => {
-                self.state = TokenizerState::T;
-            }
-            'u' => {
-                self.state = TokenizerState::U;
-            }
-            _ => {
-                crate::ocd::output::mrn_lexer_error(config.verbosity, "*Init*");
-                self.state = TokenizerState::Error;
-            }
-        }
-    }
-
-    fn state_comma(&mut self, config: &MassRenameConfig, c: char) {
-        match c {
-            ',' => {
-                self.tokens.push(Token::Comma);
-                self.state = TokenizerState::Comma;
-            }
-            ' ' => {
-                self.tokens.push(Token::Comma);
-                self.state = TokenizerState::Space;
-            }
-            '"' => {
-                self.string.clear();
-                self.state = TokenizerState::String;
-            }
-            '0' | '1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' | '9' => {
-                self.tokens.push(Token::Comma);
-                self.number.clear();
-                self.number.push(c);
-                self.state = TokenizerState::Number;
-            }
-            'c' => {
-                self.tokens.push(Token::Comma);
-                self.state = TokenizerState::C;
-            }
-            'd' => {
-                self.tokens.push(Token::Comma);
-                self.state = TokenizerState::D;
-            }
-            'e' => {
-                self.tokens.push(Token::Comma);
-                self.state = TokenizerState::E;
-            }
-            'i' => {
-                self.tokens.push(Token::Comma);
-                self.state = TokenizerState::I;
-            }
-            'l' => {
-                self.tokens.push(Token::Comma);
-                self.state = TokenizerState::L;
-            }
-            'p' => {
-                self.tokens.push(Token::Comma);
-                self.state = TokenizerState::P;
-            }
-            'r' => {
-                self.tokens.push(Token::Comma);
-                self.state = TokenizerState::R;
-            }
-            's' => {
-                self.tokens.push(Token::Comma);
-                self.state = TokenizerState::S;
-            }
-            't' => {
-                self.tokens.push(Token::Comma);
-                self.state = TokenizerState::T;
-            }
-            'u' => {
-                self.tokens.push(Token::Comma);
-                self.state = TokenizerState::U;
-            }
-            _ => {
-                crate::ocd::output::mrn_lexer_error(config.verbosity, "*Comma*");
-                self.state = TokenizerState::Error;
-            }
+                '"' => self.string(start)?,
+                '/' => self.regex(start)?,
+                _ => {
+                    self.skip(|c| !is_separator(c));
+                    self.word(start)?
+                }
+            };
+            self.lexemes.push(Lexeme {
+                token,
+                offset: start,
+                length: self.position - start,
+            });
         }
+        Ok(Tokens {
+            input: String::from(self.input),
+            lexemes: self.lexemes,
+        })
     }
 
-    fn state_space(&mut self, config: &MassRenameConfig, c: char) {
-        match c {
-            ' ' => {}
-            ',' => {
-                self.tokens.push(Token::Space);
-                self.state = TokenizerState::Comma;
-            }
-            '"' => {
-                self.tokens.push(Token::Space);
-                self.string.clear();
-                self.state = TokenizerState::String;
-            }
-            '0' | '1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' | '9' => {
-                self.tokens.push(Token::Space);
-                self.number.clear();
-                self.number.push(c);
-                self.state = TokenizerState::Number;
-            }
-            'c' => {
-                self.tokens.push(Token::Space);
-                self.state = TokenizerState::C;
-            }
-            'd' => {
-                self.tokens.push(Token::Space);
-                self.state = TokenizerState::D;
-            }
-            'e' => {
-                self.tokens.push(Token::Space);
-                self.state = TokenizerState::E;
-            }
-            'i' => {
-                self.tokens.push(Token::Space);
-                self.state = TokenizerState::I;
-            }
-            'l' => {
-                self.tokens.push(Token::Space);
-                self.state = TokenizerState::L;
-            }
-            'p' => {
-                self.tokens.push(Token::Space);
-                self.state = TokenizerState::P;
-            }
-            'r' => {
-                self.tokens.push(Token::Space);
-                self.state = TokenizerState::R;
-            }
-            's' => {
-                self.tokens.push(Token::Space);
-                self.state = TokenizerState::S;
-            }
-            't' => {
-                self.tokens.push(Token::Space);
-                self.state = TokenizerState::T;
-            }
-            'u' => {
-                self.tokens.push(Token::Space);
-                self.state = TokenizerState::U;
-            }
-            _ => {
-                crate::ocd::output::mrn_lexer_error(config.verbosity, "*Space*");
-                self.state = TokenizerState::Error;
-            }
+    /// Moves past the characters for which `f` holds.
+    fn skip(&mut self, f: impl Fn(char) -> bool) {
+        while self.chars.get(self.position).is_some_and(|c| f(*c)) {
+            self.position += 1;
         }
     }
 
-    fn state_string(&mut self, _config: &MassRenameConfig, c: char) {
-        match c {
-            '"' => {
-                self.tokens.push(Token::String {
-                    value: self.string.clone(),
-                });
-                self.string.clear();
-                self.state = TokenizerState::Init;
-            }
-            _ => {
-                self.string.push(c);
-            }
+    /// Reads the string starting with the quote at `start`.
+    fn string(&mut self, start: usize) -> Result<Token, TokenizerError> {
+        self.position += 1;
+        self.skip(|c| c != '"');
+        if self.position == self.chars.len() {
+            return Err(self.error(
+                TokenizerErrorKind::UnfinishedString,
+                start,
+                String::from("Error: unfinished string"),
+                Some(String::from("close the string with a '\"'")),
+            ));
         }
-    }
-
-    fn state_number(&mut self, config: &MassRenameConfig, c: char) {
-        match c {
-            ',' => match self.number.parse::<usize>() {
-                Ok(value) => {
-                    self.tokens.push(Token::Number { value });
-                    self.state = TokenizerState::Comma;
+        let value = self.chars[start + 1..self.position].iter().collect();
+        // The closing quote is part of the string.
+        self.position += 1;
+        Ok(Token::String { value })
+    }
+
+    /// Reads the regex between the slash at `start` and the next one that is
+    /// not escaped with a backslash.
+    fn regex(&mut self, start: usize) -> Result<Token, TokenizerError> {
+        self.position += 1;
+        let mut value = String::new();
+        loop {
+            match self.chars.get(self.position) {
+                None => {
+                    return Err(self.error(
+                        TokenizerErrorKind::UnfinishedRegex,
+                        start,
+                        String::from("Error: unfinished regex"),
+                        Some(String::from("close the regex with a '/'")),
+                    ))
                 }
-                Err(_err) => {
-                    crate::ocd::output::mrn_lexer_error(
-                        config.verbosity,
-                        format!("*Number* err: {}", _err).as_str(),
-                    );
-                    self.state = TokenizerState::Error;
+                Some('/') => break,
+                Some('\\') if self.chars.get(self.position + 1) == Some(&'/') => {
+                    value.push('/');
+                    self.position += 2;
                 }
-            },
-            ' ' => match self.number.parse::<usize>() {
-                Ok(value) => {
-                    self.tokens.push(Token::Number { value });
-                    self.state = TokenizerState::Space;
+                Some(&c) => {
+                    value.push(c);
+                    self.position += 1;
                 }
-                Err(err) => {
-                    crate::ocd::output::mrn_lexer_error(
-                        config.verbosity,
-                        format!("*Number* err: {}", err).as_str(),
-                    );
-                    self.state = TokenizerState::Error;
-                }
-            },
-            '0' | '1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' | '9' => {
-                self.number.push(c);
-                self.state = TokenizerState::Number;
-            }
-            _ => {
-                crate::ocd::output::mrn_lexer_error(
-                    config.verbosity,
-                    format!("*Number* c: {}", c).as_str(),
-                );
-                self.state = TokenizerState::Error;
-            }
-        }
-    }
-
-    fn state_c(&mut self, config: &MassRenameConfig, c: char) {
-        match c {
-            'c' => {
-                self.state = TokenizerState::CC;
-            }
-            _ => {
-                crate::ocd::output::mrn_lexer_error(config.verbosity, "*C*");
-                self.state = TokenizerState::Error;
-            }
-        }
-    }
-
-    fn state_cc(&mut self, config: &MassRenameConfig, c: char) {
-        match c {
-            'j' => {
-                self.state = TokenizerState::CCJ;
-            }
-            's' => {
-                self.state = TokenizerState::CCS;
-            }
-            _ => {
-                crate::ocd::output::mrn_lexer_error(config.verbosity, "*CC*");
-                self.state = TokenizerState::Error;
-            }
-        }
-    }
-
-    fn state_ccj(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::CamelCaseJoin, "*CCJ*")
-    }
-
-    fn state_ccs(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::CamelCaseSplit, "*CCS*")
-    }
-
-    fn state_d(&mut self, config: &MassRenameConfig, c: char) {
-        match c {
-            ',' => {
-                self.tokens.push(Token::Delete);
-                self.state = TokenizerState::Comma;
-            }
-            ' ' => {
-                self.tokens.push(Token::Delete);
-                self.state = TokenizerState::Space;
-            }
-            'p' => {
-                self.state = TokenizerState::DP;
-            }
-            's' => {
-                self.state = TokenizerState::DS;
-            }
-            'u' => {
-                self.state = TokenizerState::DU;
-            }
-            _ => {
-                crate::ocd::output::mrn_lexer_error(config.verbosity, "*D*");
-                self.state = TokenizerState::Error;
-            }
-        }
-    }
-
-    fn state_dp(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::ReplaceDashPeriod, "*DP*")
-    }
-
-    fn state_ds(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::ReplaceDashSpace, "*DS*")
-    }
-
-    fn state_du(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::ReplaceDashUnder, "*DU*")
-    }
-
-    fn state_e(&mut self, config: &MassRenameConfig, c: char) {
-        match c {
-            'a' => {
-                self.state = TokenizerState::EA;
-            }
-            'r' => {
-                self.state = TokenizerState::ER;
-            }
-            'n' => {
-                self.state = TokenizerState::EN;
-            }
-            _ => {
-                crate::ocd::output::mrn_lexer_error(config.verbosity, "*E*");
-                self.state = TokenizerState::Error;
             }
         }
+        self.position += 1;
+        Ok(Token::RegexLiteral { value })
     }
 
-    fn state_ea(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::ExtensionAdd, "*EA*")
-    }
-
-    fn state_er(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::ExtensionRemove, "*ER*")
-    }
-
-    fn state_en(&mut self, config: &MassRenameConfig, c: char) {
-        match c {
-            'd' => {
-                self.state = TokenizerState::END;
-            }
-            _ => {
-                crate::ocd::output::mrn_lexer_error(config.verbosity, "*EN*");
-                self.state = TokenizerState::Error;
-            }
-        }
-    }
-
-    fn state_end(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::End, "*END*")
-    }
-
-    fn state_i(&mut self, config: &MassRenameConfig, c: char) {
-        match c {
-            ',' => {
-                self.tokens.push(Token::Insert);
-                self.state = TokenizerState::Comma;
-            }
-            ' ' => {
-                self.tokens.push(Token::Insert);
-                self.state = TokenizerState::Space;
-            }
-            'p' => {
-                self.state = TokenizerState::IP;
-            }
-            't' => {
-                self.state = TokenizerState::IT;
-            }
-            _ => {
-                crate::ocd::output::mrn_lexer_error(config.verbosity, "*I*");
-                self.state = TokenizerState::Error;
-            }
+    /// Reads the word between `start` and the character being read.
+    fn word(&self, start: usize) -> Result<Token, TokenizerError> {
+        let word: String = self.chars[start..self.position].iter().collect();
+        if let Some(keyword) = keywords::find(&word) {
+            return Ok(keyword.token.clone());
         }
-    }
-
-    fn state_ip(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::InteractivePatternMatch, "*IP*")
-    }
-
-    fn state_it(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::InteractiveTokenize, "*IT*")
-    }
-
-    fn state_l(&mut self, config: &MassRenameConfig, c: char) {
-        match c {
-            'c' => {
-                self.state = TokenizerState::LC;
-            }
-            _ => {
-                crate::ocd::output::mrn_lexer_error(config.verbosity, "*L*");
-                self.state = TokenizerState::Error;
-            }
+        match word.as_str() {
+            END => return Ok(Token::End),
+            IF => return Ok(Token::If),
+            ELSE => return Ok(Token::Else),
+            _ => {}
         }
-    }
-
-    fn state_lc(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::LowerCase, "*LC*")
-    }
-
-    fn state_p(&mut self, config: &MassRenameConfig, c: char) {
-        match c {
-            ',' => {
-                self.tokens.push(Token::PatternMatch);
-                self.state = TokenizerState::Comma;
-            }
-            ' ' => {
-                self.tokens.push(Token::PatternMatch);
-                self.state = TokenizerState::Space;
-            }
-            's' => {
-                self.state = TokenizerState::PS;
-            }
-            'd' => {
-                self.state = TokenizerState::PD;
-            }
-            'u' => {
-                self.state = TokenizerState::PU;
-            }
-            _ => {
-                crate::ocd::output::mrn_lexer_error(config.verbosity, "*P*");
-                self.state = TokenizerState::Error;
-            }
+        if let Some((_, token)) = POSITIONS.iter().find(|(name, _)| *name == word) {
+            return Ok(token.clone());
         }
-    }
-
-    fn state_pd(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::ReplacePeriodDash, "*PD*")
-    }
-
-    fn state_ps(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::ReplacePeriodSpace, "*PS*")
-    }
-
-    fn state_pu(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::ReplacePeriodUnder, "*PU*")
-    }
-
-    fn state_r(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::Replace, "*R*")
-    }
-
-    fn state_s(&mut self, config: &MassRenameConfig, c: char) {
-        match c {
-            ',' => {
-                self.tokens.push(Token::Sanitize);
-                self.state = TokenizerState::Comma;
-            }
-            ' ' => {
-                self.tokens.push(Token::Sanitize);
-                self.state = TokenizerState::Space;
-            }
-            'c' => {
-                self.state = TokenizerState::SC;
-            }
-            'p' => {
-                self.state = TokenizerState::SP;
-            }
-            'd' => {
-                self.state = TokenizerState::SD;
-            }
-            'u' => {
-                self.state = TokenizerState::SU;
-            }
-            _ => {
-                crate::ocd::output::mrn_lexer_error(config.verbosity, "*S*");
-                self.state = TokenizerState::Error;
-            }
+        if conditions::is_condition(&word) {
+            return Ok(Token::Condition { value: word });
         }
-    }
-
-    fn state_sc(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::SentenceCase, "*SC*")
-    }
-
-    fn state_sp(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::ReplaceSpacePeriod, "*SP*")
-    }
-
-    fn state_sd(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::ReplaceSpaceDash, "*SD*")
-    }
-
-    fn state_su(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::ReplaceSpaceUnder, "*SU*")
-    }
-
-    fn state_t(&mut self, config: &MassRenameConfig, c: char) {
-        match c {
-            'c' => {
-                self.state = TokenizerState::TC;
-            }
-            _ => {
-                crate::ocd::output::mrn_lexer_error(config.verbosity, "*T*");
-                self.state = TokenizerState::Error;
-            }
+        // An index counted from the end of the name.
+        let (digits, negative) = match word.strip_prefix('-') {
+            Some(digits) if !digits.is_empty() => (digits, true),
+            _ => (word.as_str(), false),
+        };
+        if digits.starts_with(|c: char| c.is_ascii_digit()) {
+            return match digits.chars().find(|c| !c.is_ascii_digit()) {
+                Some(c) => Err(self.error(
+                    TokenizerErrorKind::Unexpected,
+                    start,
+                    format!("Error: unexpected {:?} in a number", c),
+                    None,
+                )),
+                None => digits
+                    .parse()
+                    .map(|value| {
+                        if negative {
+                            Token::NegativeNumber { value }
+                        } else {
+                            Token::Number { value }
+                        }
+                    })
+                    .map_err(|err| {
+                        self.error(
+                            TokenizerErrorKind::ParseIntError,
+                            start,
+                            format!("Error: unable to read number: {}", err),
+                            None,
+                        )
+                    }),
+            };
         }
-    }
-
-    fn state_tc(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::TitleCase, "*TC*")
-    }
-
-    fn state_u(&mut self, config: &MassRenameConfig, c: char) {
-        match c {
-            'c' => {
-                self.state = TokenizerState::UC;
-            }
-            'd' => {
-                self.state = TokenizerState::UD;
-            }
-            'p' => {
-                self.state = TokenizerState::UP;
-            }
-            's' => {
-                self.state = TokenizerState::US;
-            }
-            _ => {
-                crate::ocd::output::mrn_lexer_error(config.verbosity, "*U*");
-                self.state = TokenizerState::Error;
-            }
+        if let Some(c) = word.chars().find(|c| !c.is_alphanumeric()) {
+            return Err(self.error(
+                TokenizerErrorKind::Unexpected,
+                start,
+                format!("Error: unexpected {:?}", c),
+                None,
+            ));
         }
-    }
-
-    fn state_uc(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::UpperCase, "*UC*")
-    }
-
-    fn state_ud(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::ReplaceUnderDash, "*UD*")
-    }
-
-    fn state_us(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::ReplaceUnderSpace, "*US*")
-    }
-
-    fn state_up(&mut self, config: &MassRenameConfig, c: char) {
-        self.emit_token(config, c, Token::ReplaceUnderPeriod, "*UP*")
-    }
-
-    fn emit_token(&mut self, config: &MassRenameConfig, c: char, token: Token, error_msg: &str) {
-        match c {
-            ',' => {
-                self.tokens.push(token);
-                self.state = TokenizerState::Comma;
-            }
-            ' ' => {
-                self.tokens.push(token);
-                self.state = TokenizerState::Space;
-            }
-            _ => {
-                crate::ocd::output::mrn_lexer_error(config.verbosity, error_msg);
-                self.state = TokenizerState::Error;
-            }
+        // A word that starts a keyword was most likely cut short.
+        let completions: Vec<&str> = KEYWORDS
+            .iter()
+            .map(|keyword| keyword.name)
+            .filter(|name| name.starts_with(&word))
+            .collect();
+        let (kind, candidates) = if completions.is_empty() {
+            let mut candidates: Vec<&str> = KEYWORDS.iter().map(|keyword| keyword.name).collect();
+            candidates.extend([END, IF, ELSE]);
+            candidates.extend(POSITIONS.iter().map(|(name, _)| *name));
+            (TokenizerErrorKind::Unexpected, candidates)
+        } else {
+            (TokenizerErrorKind::UnfinishedRule, completions)
+        };
+        Err(self.error(
+            kind,
+            start,
+            format!("Error: unknown rule {:?}", word),
+            did_you_mean(&word, &candidates),
+        ))
+    }
+
+    /// Reports an error with what was read from `start` up to the character
+    /// being read.
+    fn error(
+        &self,
+        kind: TokenizerErrorKind,
+        start: usize,
+        msg: String,
+        hint: Option<String>,
+    ) -> TokenizerError {
+        TokenizerError {
+            kind,
+            msg,
+            diagnostic: Diagnostic::new(self.input, start, self.position - start).with_hint(hint),
         }
     }
 }
 
-pub fn tokenize(config: &MassRenameConfig, input: &str) -> Result<Vec<Token>, Box<dyn Error>> {
-    Tokenizer::new().run(config, input)
+pub fn tokenize(config: &MassRenameConfig, input: &str) -> Result<Tokens, TokenizerError> {
+    Tokenizer::new(input).run().inspect_err(|error| {
+        crate::ocd::output::mrn_lexer_error(config.verbosity, &format!("{:?}", error.kind));
+    })
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    /// The tokens read from `input`, without their positions.
+    fn tokens(input: &str) -> Result<Vec<Token>, TokenizerError> {
+        tokenize(&MassRenameConfig::new(), input).map(|tokens| {
+            tokens
+                .lexemes
+                .into_iter()
+                .map(|lexeme| lexeme.token)
+                .collect()
+        })
+    }
+
     #[test]
     fn empty_test() {
         let empty: [Token; 0] = [];
-        assert_eq!(
-            &empty,
-            tokenize(&MassRenameConfig::new(), "").unwrap().as_slice()
-        );
+        assert_eq!(&empty, tokens("").unwrap().as_slice());
     }
 
     #[test]
     fn comma_test() {
-        assert_eq!(
-            &[Token::Comma],
-            tokenize(&MassRenameConfig::new(), ",").unwrap().as_slice()
-        );
+        assert_eq!(&[Token::Comma], tokens(",").unwrap().as_slice());
     }
 
     #[test]
     fn space_test() {
-        assert_eq!(
-            &[Token::Space],
-            tokenize(&MassRenameConfig::new(), " ").unwrap().as_slice()
-        );
+        assert_eq!(&[Token::Space], tokens(" ").unwrap().as_slice());
     }
 
     #[test]
     fn multiple_spaces_test() {
-        assert_eq!(
-            &[Token::Space],
-            tokenize(&MassRenameConfig::new(), "   ")
-                .unwrap()
-                .as_slice()
-        );
+        assert_eq!(&[Token::Space], tokens("   ").unwrap().as_slice());
     }
 
     #[test]
@@ -976,134 +403,121 @@ mod test {
             &[Token::String {
                 value: String::from("look, a string")
             }],
-            tokenize(&MassRenameConfig::new(), "\"look, a string\"")
-                .unwrap()
-                .as_slice()
+            tokens("\"look, a string\"").unwrap().as_slice()
         );
     }
     #[test]
     fn zero_test() {
         assert_eq!(
             &[Token::Number { value: 0 }],
-            tokenize(&MassRenameConfig::new(), "0").unwrap().as_slice()
+            tokens("0").unwrap().as_slice()
         );
     }
     #[test]
     fn number_test() {
         assert_eq!(
             &[Token::Number { value: 10 }],
-            tokenize(&MassRenameConfig::new(), "10").unwrap().as_slice()
+            tokens("10").unwrap().as_slice()
         );
     }
     #[test]
     fn large_number_test() {
         assert_eq!(
             &[Token::Number { value: 105 }],
-            tokenize(&MassRenameConfig::new(), "105")
-                .unwrap()
-                .as_slice()
+            tokens("105").unwrap().as_slice()
         );
     }
 
     #[test]
     fn end_test() {
-        assert_eq!(
-            &[Token::End],
-            tokenize(&MassRenameConfig::new(), "end")
-                .unwrap()
-                .as_slice()
-        );
+        assert_eq!(&[Token::End], tokens("end").unwrap().as_slice());
     }
 
     #[test]
     fn pattern_match_test() {
-        assert_eq!(
-            &[Token::PatternMatch],
-            tokenize(&MassRenameConfig::new(), "p").unwrap().as_slice()
-        );
+        assert_eq!(&[Token::PatternMatch], tokens("p").unwrap().as_slice());
     }
 
     #[test]
     fn lower_case_test() {
-        assert_eq!(
-            &[Token::LowerCase],
-            tokenize(&MassRenameConfig::new(), "lc").unwrap().as_slice()
-        );
+        assert_eq!(&[Token::LowerCase], tokens("lc").unwrap().as_slice());
     }
 
     #[test]
     fn upper_case_test() {
-        assert_eq!(
-            &[Token::UpperCase],
-            tokenize(&MassRenameConfig::new(), "uc").unwrap().as_slice()
-        );
+        assert_eq!(&[Token::UpperCase], tokens("uc").unwrap().as_slice());
     }
 
     #[test]
     fn title_case_test() {
-        assert_eq!(
-            &[Token::TitleCase],
-            tokenize(&MassRenameConfig::new(), "tc").unwrap().as_slice()
-        );
+        assert_eq!(&[Token::TitleCase], tokens("tc").unwrap().as_slice());
     }
 
     #[test]
     fn sentence_case_test() {
-        assert_eq!(
-            &[Token::SentenceCase],
-            tokenize(&MassRenameConfig::new(), "sc").unwrap().as_slice()
-        );
+        assert_eq!(&[Token::SentenceCase], tokens("sc").unwrap().as_slice());
     }
 
     #[test]
     fn camel_case_join_test() {
-        assert_eq!(
-            &[Token::CamelCaseJoin],
-            tokenize(&MassRenameConfig::new(), "ccj")
-                .unwrap()
-                .as_slice()
-        );
+        assert_eq!(&[Token::CamelCaseJoin], tokens("ccj").unwrap().as_slice());
     }
 
     #[test]
     fn camel_case_split_test() {
+        assert_eq!(&[Token::CamelCaseSplit], tokens("ccs").unwrap().as_slice());
+    }
+
+    #[test]
+    fn identifier_case_changes_test() {
         assert_eq!(
-            &[Token::CamelCaseSplit],
-            tokenize(&MassRenameConfig::new(), "ccs")
-                .unwrap()
-                .as_slice()
+            &[
+                Token::SnakeCase,
+                Token::Comma,
+                Token::KebabCase,
+                Token::Comma,
+                Token::ScreamingSnakeCase,
+                Token::Comma,
+                Token::PascalCase,
+                Token::Comma,
+                Token::CamelCase,
+                Token::Comma,
+                Token::DotCase,
+            ],
+            tokens("snc,kc,ssc,pc,cc,dc").unwrap().as_slice()
         );
     }
 
+    #[test]
+    fn camel_case_test() {
+        assert_eq!(&[Token::CamelCase], tokens("cc").unwrap().as_slice());
+    }
+
+    #[test]
+    fn unfinished_snake_case_test() {
+        assert!(tokens("sn").is_err());
+    }
+
     #[test]
     fn extension_add_test() {
-        assert_eq!(
-            &[Token::ExtensionAdd],
-            tokenize(&MassRenameConfig::new(), "ea").unwrap().as_slice()
-        );
+        assert_eq!(&[Token::ExtensionAdd], tokens("ea").unwrap().as_slice());
     }
 
     #[test]
     fn extension_remove_test() {
-        assert_eq!(
-            &[Token::ExtensionRemove],
-            tokenize(&MassRenameConfig::new(), "er").unwrap().as_slice()
-        );
+        assert_eq!(&[Token::ExtensionRemove], tokens("er").unwrap().as_slice());
     }
 
     #[test]
     fn insert_test() {
-        assert_eq!(
-            &[Token::Insert],
-            tokenize(&MassRenameConfig::new(), "i").unwrap().as_slice()
-        );
+        assert_eq!(&[Token::Insert], tokens("i").unwrap().as_slice());
     }
 
     #[test]
     fn interactive_tokenize_test() {
         assert_eq!(
             &[Token::InteractiveTokenize],
-            tokenize(&MassRenameConfig::new(), "it").unwrap().as_slice()
+            tokens("it").unwrap().as_slice()
         );
     }
 
@@ -1111,47 +525,57 @@ mod test {
     fn interactive_pattern_match_test() {
         assert_eq!(
             &[Token::InteractivePatternMatch],
-            tokenize(&MassRenameConfig::new(), "ip").unwrap().as_slice()
+            tokens("ip").unwrap().as_slice()
         );
     }
 
     #[test]
     fn delete_test() {
-        assert_eq!(
-            &[Token::Delete],
-            tokenize(&MassRenameConfig::new(), "d").unwrap().as_slice()
-        );
+        assert_eq!(&[Token::Delete], tokens("d").unwrap().as_slice());
     }
 
     #[test]
     fn replace_test() {
+        assert_eq!(&[Token::Replace], tokens("r").unwrap().as_slice());
+    }
+
+    #[test]
+    fn regex_test() {
         assert_eq!(
-            &[Token::Replace],
-            tokenize(&MassRenameConfig::new(), "r").unwrap().as_slice()
+            &[
+                Token::Regex,
+                Token::Space,
+                Token::String {
+                    value: String::from(r"(\d+) (?P<title>.*)")
+                },
+                Token::Space,
+                Token::String {
+                    value: String::from("${title} $1")
+                },
+                Token::Comma,
+                Token::RegexCaseInsensitive,
+            ],
+            tokens(r#"x "(\d+) (?P<title>.*)" "${title} $1",xi"#)
+                .unwrap()
+                .as_slice()
         );
     }
 
     #[test]
     fn sanitize_test() {
-        assert_eq!(
-            &[Token::Sanitize],
-            tokenize(&MassRenameConfig::new(), "s").unwrap().as_slice()
-        );
+        assert_eq!(&[Token::Sanitize], tokens("s").unwrap().as_slice());
     }
 
     #[test]
     fn replace_space_dash_test() {
-        assert_eq!(
-            &[Token::ReplaceSpaceDash],
-            tokenize(&MassRenameConfig::new(), "sd").unwrap().as_slice()
-        );
+        assert_eq!(&[Token::ReplaceSpaceDash], tokens("sd").unwrap().as_slice());
     }
 
     #[test]
     fn replace_space_period_test() {
         assert_eq!(
             &[Token::ReplaceSpacePeriod],
-            tokenize(&MassRenameConfig::new(), "sp").unwrap().as_slice()
+            tokens("sp").unwrap().as_slice()
         );
     }
 
@@ -1159,39 +583,33 @@ mod test {
     fn replace_space_underscore_test() {
         assert_eq!(
             &[Token::ReplaceSpaceUnder],
-            tokenize(&MassRenameConfig::new(), "su").unwrap().as_slice()
+            tokens("su").unwrap().as_slice()
         );
     }
 
     #[test]
     fn replace_dash_space_test() {
-        assert_eq!(
-            &[Token::ReplaceDashSpace],
-            tokenize(&MassRenameConfig::new(), "ds").unwrap().as_slice()
-        );
+        assert_eq!(&[Token::ReplaceDashSpace], tokens("ds").unwrap().as_slice());
     }
 
     #[test]
     fn replace_dash_period_test() {
         assert_eq!(
             &[Token::ReplaceDashPeriod],
-            tokenize(&MassRenameConfig::new(), "dp").unwrap().as_slice()
+            tokens("dp").unwrap().as_slice()
         );
     }
 
     #[test]
     fn replace_dash_under_test() {
-        assert_eq!(
-            &[Token::ReplaceDashUnder],
-            tokenize(&MassRenameConfig::new(), "du").unwrap().as_slice()
-        );
+        assert_eq!(&[Token::ReplaceDashUnder], tokens("du").unwrap().as_slice());
     }
 
     #[test]
     fn replace_period_space_test() {
         assert_eq!(
             &[Token::ReplacePeriodSpace],
-            tokenize(&MassRenameConfig::new(), "ps").unwrap().as_slice()
+            tokens("ps").unwrap().as_slice()
         );
     }
 
@@ -1199,7 +617,7 @@ mod test {
     fn replace_period_dash_test() {
         assert_eq!(
             &[Token::ReplacePeriodDash],
-            tokenize(&MassRenameConfig::new(), "pd").unwrap().as_slice()
+            tokens("pd").unwrap().as_slice()
         );
     }
 
@@ -1207,7 +625,7 @@ mod test {
     fn replace_period_under_test() {
         assert_eq!(
             &[Token::ReplacePeriodUnder],
-            tokenize(&MassRenameConfig::new(), "pu").unwrap().as_slice()
+            tokens("pu").unwrap().as_slice()
         );
     }
 
@@ -1215,23 +633,20 @@ mod test {
     fn replace_under_space_test() {
         assert_eq!(
             &[Token::ReplaceUnderSpace],
-            tokenize(&MassRenameConfig::new(), "us").unwrap().as_slice()
+            tokens("us").unwrap().as_slice()
         );
     }
 
     #[test]
     fn replace_under_dash_test() {
-        assert_eq!(
-            &[Token::ReplaceUnderDash],
-            tokenize(&MassRenameConfig::new(), "ud").unwrap().as_slice()
-        );
+        assert_eq!(&[Token::ReplaceUnderDash], tokens("ud").unwrap().as_slice());
     }
 
     #[test]
     fn replace_underscore_period_test() {
         assert_eq!(
             &[Token::ReplaceUnderPeriod],
-            tokenize(&MassRenameConfig::new(), "up").unwrap().as_slice()
+            tokens("up").unwrap().as_slice()
         );
     }
 
@@ -1251,7 +666,7 @@ mod test {
                 Token::Comma,
                 Token::LowerCase,
             ],
-            tokenize(&MassRenameConfig::new(), "p \"{#} - {X}\" \"{1}. {2}\",lc")
+            tokens("p \"{#} - {X}\" \"{1}. {2}\",lc")
                 .unwrap()
                 .as_slice()
         );
@@ -1269,9 +684,7 @@ mod test {
                 Token::Comma,
                 Token::SentenceCase,
             ],
-            tokenize(&MassRenameConfig::new(), "lc,uc,tc,sc")
-                .unwrap()
-                .as_slice()
+            tokens("lc,uc,tc,sc").unwrap().as_slice()
         );
     }
 
@@ -1303,12 +716,9 @@ mod test {
                 Token::Comma,
                 Token::ReplaceUnderSpace,
             ],
-            tokenize(
-                &MassRenameConfig::new(),
-                "dp,ds,du,pd,ps,pu,sd,sp,su,ud,up,us"
-            )
-            .unwrap()
-            .as_slice()
+            tokens("dp,ds,du,pd,ps,pu,sd,sp,su,ud,up,us")
+                .unwrap()
+                .as_slice()
         );
     }
 
@@ -1324,9 +734,7 @@ mod test {
                     value: String::from("txt")
                 },
             ],
-            tokenize(&MassRenameConfig::new(), "er,ea \"txt\"")
-                .unwrap()
-                .as_slice()
+            tokens("er,ea \"txt\"").unwrap().as_slice()
         );
     }
 
@@ -1350,9 +758,117 @@ mod test {
                 Token::Space,
                 Token::Number { value: 0 }
             ],
-            tokenize(&MassRenameConfig::new(), "i \"text\" end,i \"text\" 0")
+            tokens("i \"text\" end,i \"text\" 0").unwrap().as_slice()
+        );
+    }
+
+    #[test]
+    fn positions_test() {
+        let tokens = tokenize(&MassRenameConfig::new(), "r \"ä b\" \"c\",  lc").unwrap();
+        let positions: Vec<(usize, usize)> = tokens
+            .lexemes
+            .iter()
+            .map(|lexeme| (lexeme.offset, lexeme.length))
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                (0, 1),
+                (1, 1),
+                (2, 5),
+                (7, 1),
+                (8, 3),
+                (11, 1),
+                (12, 2),
+                (14, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn comma_before_string_test() {
+        assert_eq!(
+            &[
+                Token::LowerCase,
+                Token::Comma,
+                Token::String {
+                    value: String::from("a")
+                }
+            ],
+            tokens("lc,\"a\"").unwrap().as_slice()
+        );
+    }
+
+    #[test]
+    fn unknown_rule_error_test() {
+        let error = tokenize(&MassRenameConfig::new(), "lc,sdd").unwrap_err();
+        assert_eq!(error.kind, TokenizerErrorKind::Unexpected);
+        assert_eq!(
+            error.diagnostic,
+            Diagnostic::new("lc,sdd", 3, 3).with_hint(Some(String::from("did you mean `sd`?")))
+        );
+        let error = tokenize(&MassRenameConfig::new(), "tc,Lc").unwrap_err();
+        assert_eq!(
+            error.diagnostic,
+            Diagnostic::new("tc,Lc", 3, 2).with_hint(Some(String::from("did you mean `lc`?")))
+        );
+        let error = tokenize(&MassRenameConfig::new(), "lc,ss").unwrap_err();
+        assert_eq!(error.kind, TokenizerErrorKind::UnfinishedRule);
+        assert_eq!(
+            error.diagnostic,
+            Diagnostic::new("lc,ss", 3, 2).with_hint(Some(String::from("did you mean `ssc`?")))
+        );
+    }
+
+    #[test]
+    fn unexpected_character_error_test() {
+        let error = tokenize(&MassRenameConfig::new(), "d 1# end").unwrap_err();
+        assert_eq!((error.diagnostic.offset, error.diagnostic.length), (2, 2));
+        let error = tokenize(&MassRenameConfig::new(), "lc,#").unwrap_err();
+        assert_eq!((error.diagnostic.offset, error.diagnostic.length), (3, 1));
+        assert_eq!(error.diagnostic.hint, None);
+        let error = tokenize(&MassRenameConfig::new(), "r \"a\" \"b").unwrap_err();
+        assert_eq!(error.kind, TokenizerErrorKind::UnfinishedString);
+        assert_eq!((error.diagnostic.offset, error.diagnostic.length), (6, 2));
+    }
+
+    #[test]
+    fn conditional_test() {
+        assert_eq!(
+            &[
+                Token::If,
+                Token::Space,
+                Token::Not,
+                Token::Condition {
+                    value: String::from("size>=1M")
+                },
+                Token::Space,
+                Token::OpenBrace,
+                Token::TitleCase,
+                Token::CloseBrace,
+                Token::Space,
+                Token::Else,
+                Token::Space,
+                Token::OpenBrace,
+                Token::Space,
+                Token::If,
+                Token::Space,
+                Token::RegexLiteral {
+                    value: String::from("a/b, c")
+                },
+                Token::Space,
+                Token::OpenBrace,
+                Token::UpperCase,
+                Token::CloseBrace,
+                Token::Space,
+                Token::CloseBrace,
+            ],
+            tokens("if !size>=1M {tc} else { if /a\\/b, c/ {uc} }")
                 .unwrap()
                 .as_slice()
         );
+        let error = tokenize(&MassRenameConfig::new(), "if /a {uc}").unwrap_err();
+        assert_eq!(error.kind, TokenizerErrorKind::UnfinishedRegex);
+        assert_eq!((error.diagnostic.offset, error.diagnostic.length), (3, 7));
     }
 }
@@ -3,25 +3,101 @@ extern crate dialoguer;
 extern crate glob;
 extern crate walkdir;
 
+pub mod cleanup;
+pub mod conditions;
+pub mod diagnostic;
+pub mod engine;
+pub mod extensions;
+pub mod generators;
+pub mod interactive;
+pub mod keywords;
 pub mod lexer;
+pub mod normalization;
 pub mod parser;
+pub mod positions;
+pub mod presets;
+pub mod sanitize;
+pub mod words;
 
 use self::walkdir::WalkDir;
-use crate::ocd::config::{directory_value, mode_value, verbosity_value, Mode, Verbosity};
+use crate::ocd::config::{directory_value, mode_value, Mode, Verbosity};
+use crate::ocd::error::OcdError;
+use crate::ocd::mrn::cleanup::Class;
+use crate::ocd::mrn::conditions::Condition;
+use crate::ocd::mrn::generators::{
+    apply_date_generators, apply_random_generators, has_random_generators,
+};
+use crate::ocd::mrn::normalization::Form;
+use crate::ocd::mrn::presets::Presets;
+use crate::ocd::mrn::sanitize::Profile;
+use crate::ocd::mrn::words::{split_words, WordKind};
+use crate::ocd::settings::Settings;
+use chrono::{Local, NaiveDateTime};
 use lazy_static::lazy_static;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use regex::Regex;
-use std::collections::BTreeMap;
-use std::error::Error;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
 use std::fs;
-use std::fs::File;
-use std::io::Write;
-use std::path::PathBuf;
-use std::process::Command;
+use std::io;
+use std::path::{Path, PathBuf};
 
+/// Where in a name a rule inserts text or starts or stops deleting it. Indices
+/// count grapheme clusters, i.e. characters as they are displayed.
 #[derive(Debug, PartialEq)]
 pub enum Position {
+    Start,
     End,
-    Index { value: usize },
+    Index {
+        value: usize,
+    },
+    /// An index counted from the end of the name.
+    FromEnd {
+        value: usize,
+    },
+    /// Right before the first occurrence of the anchor.
+    Before {
+        anchor: Anchor,
+    },
+    /// Right after the first occurrence of the anchor.
+    After {
+        anchor: Anchor,
+    },
+}
+
+/// What a position can be relative to.
+#[derive(Debug, PartialEq)]
+pub enum Anchor {
+    Digit,
+    Letter,
+    Text(String),
+    Regex(RuleRegex),
+}
+
+/// Writes a position as it is written in the rules.
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Position::Start => write!(f, "start"),
+            Position::End => write!(f, "end"),
+            Position::Index { value } => write!(f, "{}", value),
+            Position::FromEnd { value } => write!(f, "-{}", value),
+            Position::Before { anchor } => write!(f, "before {}", anchor),
+            Position::After { anchor } => write!(f, "after {}", anchor),
+        }
+    }
+}
+
+impl fmt::Display for Anchor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Anchor::Digit => write!(f, "digit"),
+            Anchor::Letter => write!(f, "letter"),
+            Anchor::Text(text) => write!(f, "{:?}", text),
+            Anchor::Regex(regex) => write!(f, "/{}/", regex.0.as_str()),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -32,7 +108,16 @@ pub enum Rule {
     SentenceCase,
     CamelCaseJoin,
     CamelCaseSplit,
-    Replace { pattern: String, replace: String },
+    SnakeCase,
+    KebabCase,
+    ScreamingSnakeCase,
+    PascalCase,
+    CamelCase,
+    DotCase,
+    Replace {
+        pattern: String,
+        replace: String,
+    },
     ReplaceSpaceDash,
     ReplaceSpacePeriod,
     ReplaceSpaceUnder,
@@ -45,14 +130,83 @@ pub enum Rule {
     ReplaceUnderDash,
     ReplaceUnderPeriod,
     ReplaceUnderSpace,
+    /// Removes the characters of a class from the start and end of the name.
+    Trim {
+        class: Class,
+    },
+    /// Replaces each run of characters of a class with its first one.
+    Collapse {
+        class: Class,
+    },
     Sanitize,
-    PatternMatch { pattern: String, replace: String },
-    ExtensionAdd { extension: String },
+    SanitizeProfile {
+        profile: Profile,
+    },
+    /// Converts the whole name, extension included, to a Unicode
+    /// normalization form.
+    Normalize {
+        form: Form,
+    },
+    PatternMatch {
+        pattern: String,
+        replace: String,
+    },
+    ExtensionAdd {
+        extension: String,
+    },
     ExtensionRemove,
-    Insert { text: String, position: Position },
+    ExtensionLowerCase,
+    ExtensionUpperCase,
+    ExtensionMap {
+        from: String,
+        to: String,
+    },
+    /// Sets the extension that the contents of the file call for, if it is
+    /// missing or wrong.
+    ExtensionDetect,
+    Insert {
+        text: String,
+        position: Position,
+    },
     InteractiveTokenize,
     InteractivePatternMatch,
-    Delete { from: usize, to: Position },
+    Delete {
+        from: Position,
+        to: Position,
+    },
+    /// The recipe built by the interactive tokenize rule: names whose words
+    /// have the given shape are rebuilt from the words at `order`, joined by
+    /// `separator`. With `rest`, the last word of the shape also takes the
+    /// words after it, as they are written, so longer names match too. Other
+    /// names are left alone.
+    Tokenize {
+        shape: Vec<WordKind>,
+        order: Vec<usize>,
+        separator: String,
+        rest: bool,
+    },
+    Regex {
+        regex: RuleRegex,
+        replace: String,
+    },
+    /// Applies `then` to the entries that meet the condition and `otherwise`
+    /// to the rest, one rule after the other.
+    Conditional {
+        condition: Condition,
+        then: Vec<Rule>,
+        otherwise: Vec<Rule>,
+    },
+}
+
+/// A regular expression in a rule, compiled when the rules are parsed.
+/// Two of them are equal if they were compiled from the same expression.
+#[derive(Clone, Debug)]
+pub struct RuleRegex(pub Regex);
+
+impl PartialEq for RuleRegex {
+    fn eq(&self, other: &RuleRegex) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -63,10 +217,17 @@ pub struct MassRenameConfig {
     pub dryrun: bool,
     pub git: bool,
     pub recurse: bool,
-    pub undo: bool,
     pub yes: bool,
     pub glob: Option<String>,
     pub rules_raw: Option<String>,
+    pub num_start: usize,
+    pub num_step: usize,
+    pub num_reset: bool,
+    pub check_normalization: bool,
+    pub seed: Option<u64>,
+    pub journal: PathBuf,
+    pub presets: PathBuf,
+    pub compound_extensions: Vec<String>,
 }
 
 impl MassRenameConfig {
@@ -78,316 +239,592 @@ impl MassRenameConfig {
             dryrun: true,
             git: false,
             recurse: false,
-            undo: false,
             yes: false,
             glob: None,
             rules_raw: None,
+            num_start: 1,
+            num_step: 1,
+            num_reset: false,
+            check_normalization: false,
+            seed: None,
+            journal: crate::ocd::journal::default_path(),
+            presets: crate::ocd::mrn::presets::default_path(),
+            compound_extensions: extensions::COMPOUND
+                .iter()
+                .map(|extension| String::from(*extension))
+                .collect(),
         }
     }
 
-    pub fn with_args(&self, matches: &clap::ArgMatches) -> MassRenameConfig {
-        fn glob_value(glob: Option<&str>) -> Option<String> {
-            glob.map(String::from)
-        }
-
-        fn rules_value(matches: &clap::ArgMatches) -> Option<String> {
-            matches
-                .value_of("rules")
-                .map(|rules_input| rules_input.to_string())
-        }
+    pub fn with_args(&self, settings: &Settings) -> Result<MassRenameConfig, String> {
+        Ok(MassRenameConfig {
+            verbosity: settings.verbosity()?,
+            mode: mode_value(&settings.choice("mode", &["a", "d", "f"])?.unwrap()),
+            dir: directory_value(&settings.value("dir")?.unwrap()),
+            dryrun: settings.flag("dry-run")?,
+            git: settings.flag("git")?,
+            recurse: settings.flag("recurse")?,
+            yes: settings.flag("yes")?,
+            glob: settings.value("glob")?,
+            rules_raw: settings.value("rules")?,
+            num_start: settings.parse("num-start")?.unwrap_or(self.num_start),
+            num_step: settings.parse("num-step")?.unwrap_or(self.num_step),
+            num_reset: settings.flag("num-reset")?,
+            check_normalization: settings.flag("check-normalization")?,
+            seed: settings.parse("seed")?,
+            journal: self.journal.clone(),
+            presets: settings
+                .value("presets")?
+                .map_or_else(|| self.presets.clone(), PathBuf::from),
+            compound_extensions: settings
+                .list("compound-extensions")?
+                .map(|extensions| {
+                    extensions
+                        .iter()
+                        .map(|extension| String::from(extension.trim_start_matches('.')))
+                        .collect()
+                })
+                .unwrap_or_else(|| self.compound_extensions.clone()),
+        })
+    }
 
-        MassRenameConfig {
-            verbosity: verbosity_value(matches),
-            mode: mode_value(matches.value_of("mode").unwrap()),
-            dir: directory_value(matches.value_of("dir").unwrap()),
-            dryrun: matches.is_present("dry-run"),
-            git: matches.is_present("git"),
-            recurse: matches.is_present("recurse"),
-            undo: matches.is_present("undo"),
-            yes: matches.is_present("yes"),
-            glob: glob_value(matches.value_of("glob")),
-            rules_raw: rules_value(matches),
-        }
+    /// The values of the settings that can be read from the configuration
+    /// files.
+    pub fn settings(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("verbosity", self.verbosity.to_string()),
+            ("mode", self.mode.to_string()),
+            ("dir", self.dir.display().to_string()),
+            ("dry-run", self.dryrun.to_string()),
+            ("git", self.git.to_string()),
+            ("recurse", self.recurse.to_string()),
+            ("yes", self.yes.to_string()),
+            ("glob", self.glob.clone().unwrap_or_default()),
+            ("num-start", self.num_start.to_string()),
+            ("num-step", self.num_step.to_string()),
+            ("num-reset", self.num_reset.to_string()),
+            ("check-normalization", self.check_normalization.to_string()),
+            (
+                "seed",
+                self.seed.map(|seed| seed.to_string()).unwrap_or_default(),
+            ),
+            ("presets", self.presets.display().to_string()),
+            ("compound-extensions", self.compound_extensions.join(",")),
+        ]
     }
 }
 
-pub fn run(config: &MassRenameConfig) -> Result<(), Box<dyn Error>> {
-    let rules_raw = config.rules_raw.clone().unwrap();
-    let tokens = crate::ocd::mrn::lexer::tokenize(&config, &rules_raw)?;
-    let rules = crate::ocd::mrn::parser::parse(&config, &tokens)?;
-    let files = entries(&config)?;
+pub fn run(config: &MassRenameConfig) -> Result<(), OcdError> {
+    let rules_raw = crate::ocd::mrn::presets::expand(
+        config.rules_raw.as_deref().unwrap(),
+        &mut Presets::new(&config.presets),
+    )?;
+    let tokens = crate::ocd::mrn::lexer::tokenize(config, &rules_raw)?;
+    let rules = crate::ocd::mrn::parser::parse(config, &tokens)?;
+    let files = entries(config)?;
 
     crate::ocd::output::mrn_state(config, &tokens, &rules, &files);
 
-    let buffer = apply_rules(&config, &rules, &files)?;
-
-    if !config.dryrun && config.undo {
-        create_undo_script(config, &buffer);
+    let names = apply_rules(config, &rules, &files)?;
+    let buffer = clean_buffer(&names);
+    crate::ocd::output::mrn_result(config.verbosity, &buffer);
+    let moves = crate::ocd::mrn::engine::plan(&buffer)?;
+    if config.check_normalization {
+        check_normalization(config, &names)?;
     }
 
     if config.yes || crate::ocd::input::user_confirm() {
-        execute_rules(&config, &buffer)?
+        crate::ocd::mrn::engine::execute(config, &moves)?
     }
     Ok(())
 }
 
-fn entries(config: &MassRenameConfig) -> Result<Vec<PathBuf>, String> {
-    /*
-    recurse | glob | mode
-    F       | none | f
-    F       | none | m
-    F       | none | b
-    F       | some | f
-    F       | some | m
-    F       | some | b
-    T       | none | f
-    T       | none | m
-    T       | none | b
-    T       | some | f
-    T       | some | m
-    T       | some | b
-    */
+/// Fails if entries would get names that only differ in their Unicode
+/// normalization, after listing all of them. `names` holds the new name of
+/// every entry, renamed or not, and the other entries in the directories they
+/// end up in are checked against them as well.
+fn check_normalization(
+    config: &MassRenameConfig,
+    names: &BTreeMap<PathBuf, PathBuf>,
+) -> Result<(), OcdError> {
+    let mut all = names.clone();
+    let directories: BTreeSet<&Path> = names.values().filter_map(|dst| dst.parent()).collect();
+    for directory in directories {
+        let directory = if directory.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            directory
+        };
+        let iterator = match fs::read_dir(directory) {
+            Ok(iterator) => iterator,
+            // The directory itself may only exist once the rules are applied.
+            Err(reason) if reason.kind() == io::ErrorKind::NotFound => continue,
+            Err(reason) => return Err(OcdError::filesystem(directory, reason)),
+        };
+        for entry in iterator {
+            let path = entry
+                .map_err(|reason| OcdError::filesystem(directory, reason))?
+                .path();
+            all.entry(path.clone()).or_insert(path);
+        }
+    }
+    let groups = normalization::collisions(&all);
+    match groups.first() {
+        Some(group) => {
+            crate::ocd::output::mrn_normalization(config.verbosity, &groups);
+            Err(OcdError::Normalization {
+                src: group[1].0.clone(),
+                dst: PathBuf::from(normalization::apply(
+                    &group[0].1.to_string_lossy(),
+                    Form::Nfc,
+                )),
+                other: group[0].0.clone(),
+            })
+        }
+        None => Ok(()),
+    }
+}
+
+fn entries(config: &MassRenameConfig) -> Result<Vec<PathBuf>, OcdError> {
+    let wanted = |file_type: fs::FileType| match config.mode {
+        Mode::Files => file_type.is_file(),
+        Mode::Directories => file_type.is_dir(),
+        Mode::All => true,
+    };
     let mut entries_vec: Vec<PathBuf> = Vec::new();
 
-    match (config.recurse, &config.glob, &config.mode) {
-        (false, None, Mode::Files) => match fs::read_dir(&config.dir) {
-            Ok(iterator) => {
-                for entry in iterator {
-                    match entry {
-                        Ok(file) => {
-                            if file.file_type().unwrap().is_file() {
-                                entries_vec.push(file.path());
-                            }
-                        }
-                        Err(err) => return Err(format!("Error while listing files: {:?}", err)),
-                    }
-                }
-            }
-            Err(err) => return Err(format!("Error while listing files: {:?}", err)),
-        },
-        (false, None, Mode::Directories) => match fs::read_dir(&config.dir) {
-            Ok(iterator) => {
-                for entry in iterator {
-                    match entry {
-                        Ok(file) => {
-                            if file.file_type().unwrap().is_dir() {
-                                entries_vec.push(file.path());
-                            }
-                        }
-                        Err(err) => return Err(format!("Error while listing files: {:?}", err)),
-                    }
-                }
-            }
-            Err(err) => return Err(format!("Error while listing files: {:?}", err)),
-        },
-        (false, None, Mode::All) => match fs::read_dir(&config.dir) {
-            Ok(iterator) => {
-                for entry in iterator {
-                    match entry {
-                        Ok(file) => {
-                            entries_vec.push(file.path());
-                        }
-                        Err(_err) => return Err(String::from("Error while listing files")),
-                    }
-                }
-            }
-            Err(_err) => return Err(String::from("Error while listing files")),
-        },
-        (true, None, Mode::Files) => {
-            let iter = WalkDir::new(&config.dir).into_iter();
-            for entry in iter {
-                match entry {
-                    Ok(entry) => {
-                        if entry.file_type().is_file() {
-                            entries_vec.push(entry.path().to_path_buf());
-                        }
-                    }
-                    Err(_err) => return Err(String::from("Error listing files")),
+    match (config.recurse, &config.glob) {
+        (false, None) => {
+            let iterator = fs::read_dir(&config.dir)
+                .map_err(|reason| OcdError::filesystem(&config.dir, reason))?;
+            for entry in iterator {
+                let entry = entry.map_err(|reason| OcdError::filesystem(&config.dir, reason))?;
+                let file_type = entry
+                    .file_type()
+                    .map_err(|reason| OcdError::filesystem(&entry.path(), reason))?;
+                if wanted(file_type) {
+                    entries_vec.push(entry.path());
                 }
             }
         }
-        (true, None, Mode::Directories) => {
-            let iter = WalkDir::new(&config.dir).into_iter();
-            for entry in iter {
-                match entry {
-                    Ok(entry) => {
-                        if entry.file_type().is_dir() {
-                            entries_vec.push(entry.path().to_path_buf());
-                        }
+        (true, None) => {
+            for entry in WalkDir::new(&config.dir) {
+                let entry = entry.map_err(|reason| {
+                    let path = reason.path().unwrap_or(&config.dir).to_path_buf();
+                    OcdError::Filesystem {
+                        path,
+                        source: reason.into(),
                     }
-                    Err(_err) => return Err(String::from("Error listing files")),
+                })?;
+                if wanted(entry.file_type()) {
+                    entries_vec.push(entry.into_path());
                 }
             }
         }
-        (true, None, Mode::All) => {
-            let iter = WalkDir::new(&config.dir).into_iter();
-            for entry in iter {
-                entries_vec.push(entry.unwrap().path().to_path_buf());
-            }
-        }
-        (_, Some(ref glob_input), Mode::Files) => {
-            let mut path = config.dir.clone();
-            path.push(glob_input);
-            let glob_path = path.as_path().to_str().unwrap();
-            for entry in glob::glob(glob_path).unwrap().filter_map(Result::ok) {
-                let metadata = fs::metadata(&entry).unwrap();
-                if metadata.is_file() {
+        (_, Some(ref glob_input)) => {
+            let path = config.dir.join(glob_input);
+            let glob_path = path.to_string_lossy();
+            let paths = glob::glob(&glob_path).map_err(|reason| OcdError::Parse {
+                message: format!("Glob error: invalid pattern {:?}: {}", glob_input, reason),
+                diagnostic: None,
+            })?;
+            for entry in paths.filter_map(Result::ok) {
+                let metadata =
+                    fs::metadata(&entry).map_err(|reason| OcdError::filesystem(&entry, reason))?;
+                if wanted(metadata.file_type()) {
                     entries_vec.push(entry);
                 }
             }
         }
-        (_, Some(ref glob_input), Mode::Directories) => {
-            let mut path = config.dir.clone();
-            path.push(glob_input);
-            let glob_path = path.as_path().to_str().unwrap();
-            for entry in glob::glob(glob_path).unwrap().filter_map(Result::ok) {
-                let metadata = fs::metadata(&entry).unwrap();
-                if metadata.is_dir() {
-                    entries_vec.push(entry);
-                }
-            }
-        }
-        (_, Some(ref glob_input), Mode::All) => {
-            let mut path = config.dir.clone();
-            path.push(glob_input);
-            let glob_path = path.as_path().to_str().unwrap();
-            for entry in glob::glob(glob_path).unwrap().filter_map(Result::ok) {
-                entries_vec.push(entry);
-            }
-        }
     }
     Ok(entries_vec)
 }
 
+/// The new name of every entry, including those that the rules leave alone.
 fn apply_rules(
     config: &MassRenameConfig,
     rules: &[Rule],
     files: &[PathBuf],
-) -> Result<BTreeMap<PathBuf, PathBuf>, String> {
+) -> Result<BTreeMap<PathBuf, PathBuf>, OcdError> {
     let mut buffer = new_buffer(files);
+    let numbers = sequence_numbers(config, &buffer);
+    // Every file is stamped with the same time, however long the run takes.
+    let now = Local::now().naive_local();
+    let mut rng = match config.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let compound = &config.compound_extensions;
 
     for rule in rules {
-        for (index, (_src, mut dst)) in buffer.iter_mut().enumerate() {
-            apply_rule(index, &rule, &mut dst);
-        }
+        let resolved;
+        let rule = match rule {
+            Rule::InteractivePatternMatch | Rule::InteractiveTokenize => {
+                // The preview works on copies, so that accepting the rule
+                // gives exactly the names that were shown.
+                let preview = |rule: &Rule| {
+                    let mut preview = buffer.clone();
+                    apply_rule_to_buffer(
+                        rule,
+                        &mut preview,
+                        &numbers,
+                        compound,
+                        now,
+                        &mut rng.clone(),
+                        Verbosity::Silent,
+                    )?;
+                    Ok(buffer
+                        .values()
+                        .cloned()
+                        .zip(preview.into_values())
+                        .collect())
+                };
+                let chosen = if let Rule::InteractivePatternMatch = rule {
+                    crate::ocd::mrn::interactive::pattern_match(preview)?
+                } else {
+                    match buffer.values().next() {
+                        Some(sample) => crate::ocd::mrn::interactive::tokenize(
+                            &extensions::stem(sample, compound),
+                            preview,
+                        )?,
+                        None => None,
+                    }
+                };
+                match chosen {
+                    Some(rule) => {
+                        resolved = rule;
+                        &resolved
+                    }
+                    None => continue,
+                }
+            }
+            rule => rule,
+        };
+        apply_rule_to_buffer(
+            rule,
+            &mut buffer,
+            &numbers,
+            compound,
+            now,
+            &mut rng,
+            config.verbosity,
+        )?;
+    }
+
+    Ok(buffer)
+}
+
+fn apply_rule_to_buffer(
+    rule: &Rule,
+    buffer: &mut BTreeMap<PathBuf, PathBuf>,
+    numbers: &[usize],
+    compound: &[String],
+    now: NaiveDateTime,
+    rng: &mut StdRng,
+    verbosity: Verbosity,
+) -> Result<(), OcdError> {
+    let mut generated = HashSet::new();
+    for ((src, dst), number) in buffer.iter_mut().zip(numbers) {
+        let mut context = FileContext {
+            src,
+            number: *number,
+            now,
+            rng,
+            generated: &mut generated,
+            compound,
+            verbosity,
+        };
+        apply_rule(&mut context, rule, dst).map_err(|message| OcdError::Rule {
+            rule: format!("{:?}", rule),
+            path: src.clone(),
+            message,
+        })?;
     }
+    Ok(())
+}
 
-    let clean_buffer = clean_buffer(buffer);
-    crate::ocd::output::mrn_result(config.verbosity, &clean_buffer);
-    Ok(clean_buffer)
+/// The value of the `{num}` counter for each file, in the order of the buffer.
+/// The counter restarts in every directory if `num_reset` is set.
+fn sequence_numbers(config: &MassRenameConfig, buffer: &BTreeMap<PathBuf, PathBuf>) -> Vec<usize> {
+    let mut counts: HashMap<Option<&Path>, usize> = HashMap::new();
+    buffer
+        .keys()
+        .map(|src| {
+            let directory = if config.num_reset { src.parent() } else { None };
+            let count = counts.entry(directory).or_insert(0);
+            let number = config.num_start + *count * config.num_step;
+            *count += 1;
+            number
+        })
+        .collect()
 }
 
-fn apply_rule(index: usize, rule: &Rule, path: &mut PathBuf) {
-    let filename = path.file_stem().unwrap();
-    let filename = filename.to_str().unwrap();
+/// What the data generators of a pattern match need to know about a file,
+/// besides its current name.
+struct FileContext<'a> {
+    /// Where the file currently is on disk.
+    src: &'a Path,
+    /// The value of the `{num}` counter.
+    number: usize,
+    /// The time the rules are applied at.
+    now: NaiveDateTime,
+    /// The source of the random numbers, shared by all files so that a seed
+    /// reproduces the whole run.
+    rng: &'a mut StdRng,
+    /// The names given to files by random generators of the current rule.
+    generated: &'a mut HashSet<PathBuf>,
+    /// The extensions made of several parts, which are kept whole.
+    compound: &'a [String],
+    /// How much to report while applying the rules, silent for previews.
+    verbosity: Verbosity,
+}
+
+/// How many times a random name is drawn again when it collides with one given
+/// to another file.
+const RANDOM_ATTEMPTS: usize = 100;
+
+fn apply_rule(context: &mut FileContext, rule: &Rule, path: &mut PathBuf) -> Result<(), String> {
+    let filename = extensions::stem(path, context.compound);
+    let filename = filename.as_str();
     match rule {
         Rule::LowerCase => {
             let filename = apply_lower_case(filename);
-            rename_file(path, filename);
+            extensions::set_stem(path, &filename, context.compound);
         }
         Rule::UpperCase => {
             let filename = apply_upper_case(filename);
-            rename_file(path, filename);
+            extensions::set_stem(path, &filename, context.compound);
         }
         Rule::TitleCase => {
             let filename = apply_title_case(filename);
-            rename_file(path, filename);
+            extensions::set_stem(path, &filename, context.compound);
         }
         Rule::SentenceCase => {
             let filename = apply_sentence_case(filename);
-            rename_file(path, filename);
+            extensions::set_stem(path, &filename, context.compound);
         }
         Rule::CamelCaseJoin => {
             let filename = apply_camel_case_join(filename);
-            rename_file(path, filename);
+            extensions::set_stem(path, &filename, context.compound);
         }
         Rule::CamelCaseSplit => {
             let filename = apply_camel_case_split(filename);
-            rename_file(path, filename);
+            extensions::set_stem(path, &filename, context.compound);
+        }
+        Rule::SnakeCase => {
+            let filename = apply_snake_case(filename);
+            extensions::set_stem(path, &filename, context.compound);
+        }
+        Rule::KebabCase => {
+            let filename = apply_kebab_case(filename);
+            extensions::set_stem(path, &filename, context.compound);
+        }
+        Rule::ScreamingSnakeCase => {
+            let filename = apply_screaming_snake_case(filename);
+            extensions::set_stem(path, &filename, context.compound);
+        }
+        Rule::PascalCase => {
+            let filename = apply_pascal_case(filename);
+            extensions::set_stem(path, &filename, context.compound);
+        }
+        Rule::CamelCase => {
+            let filename = apply_camel_case(filename);
+            extensions::set_stem(path, &filename, context.compound);
+        }
+        Rule::DotCase => {
+            let filename = apply_dot_case(filename);
+            extensions::set_stem(path, &filename, context.compound);
         }
         Rule::Sanitize => {
             let filename = apply_sanitize(filename);
-            rename_file(path, filename);
+            extensions::set_stem(path, &filename, context.compound);
+        }
+        Rule::SanitizeProfile { profile } => {
+            let filename = sanitize::apply(filename, *profile);
+            extensions::set_stem(path, &filename, context.compound);
+        }
+        Rule::Normalize { form } => {
+            let name = normalization::apply(
+                &path.file_name().unwrap_or_default().to_string_lossy(),
+                *form,
+            );
+            path.set_file_name(name);
         }
         Rule::Replace { pattern, replace } => {
             let filename = apply_replace(filename, pattern, replace);
-            rename_file(path, filename);
+            extensions::set_stem(path, &filename, context.compound);
         }
         Rule::ReplaceSpaceDash => {
             let filename = apply_replace(filename, " ", "-");
-            rename_file(path, filename);
+            extensions::set_stem(path, &filename, context.compound);
         }
         Rule::ReplaceSpacePeriod => {
             let filename = apply_replace(filename, " ", ".");
-            rename_file(path, filename);
+            extensions::set_stem(path, &filename, context.compound);
         }
         Rule::ReplaceSpaceUnder => {
             let filename = apply_replace(filename, " ", "_");
-            rename_file(path, filename);
+            extensions::set_stem(path, &filename, context.compound);
         }
         Rule::ReplaceDashPeriod => {
             let filename = apply_replace(filename, "-", ".");
-            rename_file(path, filename);
+            extensions::set_stem(path, &filename, context.compound);
         }
         Rule::ReplaceDashSpace => {
             let filename = apply_replace(filename, "-", " ");
-            rename_file(path, filename);
+            extensions::set_stem(path, &filename, context.compound);
         }
         Rule::ReplaceDashUnder => {
             let filename = apply_replace(filename, "-", "_");
-            rename_file(path, filename);
+            extensions::set_stem(path, &filename, context.compound);
         }
         Rule::ReplacePeriodDash => {
             let filename = apply_replace(filename, ".", "-");
-            rename_file(path, filename);
+            extensions::set_stem(path, &filename, context.compound);
         }
         Rule::ReplacePeriodSpace => {
             let filename = apply_replace(filename, ".", " ");
-            rename_file(path, filename);
+            extensions::set_stem(path, &filename, context.compound);
         }
         Rule::ReplacePeriodUnder => {
             let filename = apply_replace(filename, ".", "_");
-            rename_file(path, filename);
+            extensions::set_stem(path, &filename, context.compound);
         }
         Rule::ReplaceUnderDash => {
             let filename = apply_replace(filename, "_", "-");
-            rename_file(path, filename);
+            extensions::set_stem(path, &filename, context.compound);
         }
         Rule::ReplaceUnderPeriod => {
             let filename = apply_replace(filename, "_", ".");
-            rename_file(path, filename);
+            extensions::set_stem(path, &filename, context.compound);
         }
         Rule::ReplaceUnderSpace => {
             let filename = apply_replace(filename, "_", " ");
-            rename_file(path, filename);
+            extensions::set_stem(path, &filename, context.compound);
+        }
+        Rule::Trim { class } => {
+            let filename = cleanup::trim(filename, *class);
+            extensions::set_stem(path, &filename, context.compound);
+        }
+        Rule::Collapse { class } => {
+            let filename = cleanup::collapse(filename, *class);
+            extensions::set_stem(path, &filename, context.compound);
         }
         Rule::PatternMatch { pattern, replace } => {
-            let filename = apply_pattern_match(index, filename, pattern, replace);
-            rename_file(path, filename);
+            // A file the pattern doesn't match keeps its name, so it doesn't
+            // need the dates that the replacement would take from it.
+            let unmatched =
+                pattern_match_regex(pattern).is_ok_and(|regex| !regex.is_match(filename));
+            let replace = if unmatched {
+                replace.clone()
+            } else {
+                apply_date_generators(replace, context.now, context.src)?
+            };
+            crate::ocd::output::mrn_pattern_match(context.verbosity, filename, pattern, &replace);
+            if unmatched && context.verbosity.is_high() {
+                crate::ocd::output::mrn_no_match(context.verbosity, filename);
+            }
+            if has_random_generators(&replace) {
+                apply_unique_random_pattern_match(context, path, pattern, &replace)?;
+            } else {
+                let filename = apply_pattern_match(context.number, filename, pattern, &replace)?;
+                extensions::set_stem(path, &filename, context.compound);
+            }
         }
         Rule::ExtensionAdd { extension } => {
-            path.set_extension(extension);
+            extensions::set_extension(path, extension, context.compound);
         }
         Rule::ExtensionRemove => {
-            path.set_extension("");
+            extensions::set_extension(path, "", context.compound);
+        }
+        Rule::ExtensionLowerCase => {
+            if let Some(extension) = extensions::extension(path, context.compound) {
+                extensions::set_extension(path, &extension.to_lowercase(), context.compound);
+            }
+        }
+        Rule::ExtensionUpperCase => {
+            if let Some(extension) = extensions::extension(path, context.compound) {
+                extensions::set_extension(path, &extension.to_uppercase(), context.compound);
+            }
+        }
+        Rule::ExtensionMap { from, to } => {
+            if extensions::has_extension(path, from, context.compound) {
+                extensions::set_extension(path, to, context.compound);
+            }
+        }
+        Rule::ExtensionDetect => {
+            if let Some(extension) = extensions::detect(context.src, path, context.compound)? {
+                extensions::set_extension(path, extension, context.compound);
+            }
         }
         Rule::Insert { text, position } => {
             let filename = apply_insert(filename, text, position);
-            rename_file(path, filename);
+            extensions::set_stem(path, &filename, context.compound);
         }
-        Rule::InteractiveTokenize => {
-            let filename = apply_interactive_tokenize(filename);
-            rename_file(path, filename);
-        }
-        Rule::InteractivePatternMatch => {
-            let filename = apply_interactive_pattern_match(filename);
-            rename_file(path, filename);
+        Rule::InteractiveTokenize | Rule::InteractivePatternMatch => {
+            unreachable!("interactive rules are resolved by apply_rules")
         }
         Rule::Delete { from, to } => {
-            let filename = apply_delete(filename, *from, to);
-            rename_file(path, filename);
+            let filename = apply_delete(filename, from, to)?;
+            extensions::set_stem(path, &filename, context.compound);
+        }
+        Rule::Tokenize {
+            shape,
+            order,
+            separator,
+            rest,
+        } => {
+            let filename = apply_tokenize(filename, shape, order, separator, *rest);
+            extensions::set_stem(path, &filename, context.compound);
+        }
+        Rule::Regex { regex, replace } => {
+            let filename = apply_regex(filename, &regex.0, replace);
+            extensions::set_stem(path, &filename, context.compound);
+        }
+        Rule::Conditional {
+            condition,
+            then,
+            otherwise,
+        } => {
+            let rules = if condition.holds(context.src, path, context.compound)? {
+                then
+            } else {
+                otherwise
+            };
+            for rule in rules {
+                apply_rule(context, rule, path)?;
+            }
         }
     }
+    Ok(())
+}
+
+/// Applies a pattern match with random generators, drawing the random numbers
+/// again while the result collides with a name given to another file.
+fn apply_unique_random_pattern_match(
+    context: &mut FileContext,
+    path: &mut PathBuf,
+    pattern: &str,
+    replace: &str,
+) -> Result<(), String> {
+    let filename = extensions::stem(path, context.compound);
+    for _ in 0..RANDOM_ATTEMPTS {
+        let replace = apply_random_generators(replace, context.rng)?;
+        let mut candidate = path.clone();
+        extensions::set_stem(
+            &mut candidate,
+            &apply_pattern_match(context.number, &filename, pattern, &replace)?,
+            context.compound,
+        );
+        if context.generated.insert(candidate.clone()) {
+            *path = candidate;
+            return Ok(());
+        }
+    }
+    Err(format!(
+        "Pattern error: unable to generate a unique name for {:?}, the range of the random numbers is too small",
+        context.src
+    ))
 }
 
 fn apply_lower_case(filename: &str) -> String {
@@ -445,12 +882,64 @@ fn titlecase_word(word: &str) -> String {
     titlecase_word
 }
 
-fn apply_camel_case_join(_filename: &str) -> String {
-    unimplemented!()
+fn apply_camel_case_join(filename: &str) -> String {
+    split_words(filename)
+        .iter()
+        .map(|word| titlecase_word(word))
+        .collect()
 }
 
-fn apply_camel_case_split(_filename: &str) -> String {
-    unimplemented!()
+fn apply_camel_case_split(filename: &str) -> String {
+    split_words(filename)
+        .iter()
+        .map(|word| titlecase_word(word))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn apply_snake_case(filename: &str) -> String {
+    join_lowercase_words(filename, "_")
+}
+
+fn apply_kebab_case(filename: &str) -> String {
+    join_lowercase_words(filename, "-")
+}
+
+fn apply_screaming_snake_case(filename: &str) -> String {
+    split_words(filename)
+        .iter()
+        .map(|word| word.to_uppercase())
+        .collect::<Vec<String>>()
+        .join("_")
+}
+
+fn apply_pascal_case(filename: &str) -> String {
+    apply_camel_case_join(filename)
+}
+
+fn apply_camel_case(filename: &str) -> String {
+    let words = split_words(filename);
+    if let Some((first_word, remaining_words)) = words.split_first() {
+        let mut camelcase_words = vec![first_word.to_lowercase()];
+        for word in remaining_words {
+            camelcase_words.push(titlecase_word(word));
+        }
+        camelcase_words.concat()
+    } else {
+        String::new()
+    }
+}
+
+fn apply_dot_case(filename: &str) -> String {
+    join_lowercase_words(filename, ".")
+}
+
+fn join_lowercase_words(filename: &str, separator: &str) -> String {
+    split_words(filename)
+        .iter()
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<String>>()
+        .join(separator)
 }
 
 fn apply_sanitize(filename: &str) -> String {
@@ -470,48 +959,97 @@ fn apply_replace(filename: &str, pattern: &str, replace: &str) -> String {
 }
 
 fn apply_pattern_match(
-    _index: usize,
+    number: usize,
     filename: &str,
     match_pattern: &str,
     replace_pattern: &str,
-) -> String {
-    fn month_to_number(month: &str) -> &str {
-        match month {
-            "jan" | "Jan" | "january" | "January" => "01",
-            "feb" | "Feb" | "february" | "February" => "02",
-            "mar" | "Mar" | "march" | "March" => "03",
-            "apr" | "Apr" | "april" | "April" => "04",
-            "may" | "May" => "05",
-            "jun" | "Jun" | "june" | "June" => "06",
-            "jul" | "Jul" | "july" | "July" => "07",
-            "aug" | "Aug" | "august" | "August" => "08",
-            "sep" | "Sep" | "september" | "September" => "09",
-            "oct" | "Oct" | "october" | "October" => "10",
-            "nov" | "Nov" | "november" | "November" => "11",
-            "dec" | "Dec" | "december" | "December" => "12",
-            unexpected => {
-                panic!("Unknown month value! {}", unexpected);
-            }
+) -> Result<String, String> {
+    fn month_to_number(month: &str) -> Result<&'static str, String> {
+        match month.to_lowercase().as_str() {
+            "jan" | "january" => Ok("01"),
+            "feb" | "february" => Ok("02"),
+            "mar" | "march" => Ok("03"),
+            "apr" | "april" => Ok("04"),
+            "may" => Ok("05"),
+            "jun" | "june" => Ok("06"),
+            "jul" | "july" => Ok("07"),
+            "aug" | "august" => Ok("08"),
+            "sep" | "september" => Ok("09"),
+            "oct" | "october" => Ok("10"),
+            "nov" | "november" => Ok("11"),
+            "dec" | "december" => Ok("12"),
+            _ => Err(format!("Pattern error: unknown month {:?}", month)),
         }
     }
 
-    crate::ocd::output::mrn_pattern_match(
-        Verbosity::Debug,
-        filename,
-        match_pattern,
-        replace_pattern,
-    );
-
     lazy_static! {
         static ref FLORB_REGEX: Regex = Regex::new(r"\{[aA]\}|\{[nN]\}|\{[xX]\}|\{[dD]\}").unwrap();
+        // This regex recognizes human-readable dates and its subparts
+        static ref IOS_DATE_FORMAT_REGEX: Regex = Regex::new(r"(?i)(?P<d>\d{1,2})\s(?P<m>January|February|March|April|May|June|July|August|September|October|November|December)\s(?P<y>\d{1,4})").unwrap();
     }
 
     let florbs: Vec<&str> = FLORB_REGEX
-        .captures_iter(&match_pattern)
+        .captures_iter(match_pattern)
         .map(|c: regex::Captures| c.get(0).unwrap().as_str())
         .collect();
 
-    // println!("florbs in match pattern: {:?}", florbs);
+    if let Some(florb) = florbs.iter().find(|florb| florb.to_uppercase() != **florb) {
+        return Err(format!(
+            "Pattern error: unknown placeholder {} in {:?}, did you mean {}?",
+            florb,
+            match_pattern,
+            florb.to_uppercase()
+        ));
+    }
+    let match_regex = pattern_match_regex(match_pattern).map_err(|error| {
+        format!(
+            "Pattern error: invalid match pattern {:?}\n{}",
+            match_pattern, error
+        )
+    })?;
+    match match_regex.captures(filename) {
+        None => Ok(String::from(filename)),
+        Some(capture) => {
+            // Counters are expanded before the captures are substituted, so
+            // that text taken from the file name is never treated as one.
+            let mut replace_pattern = apply_sequence_number(replace_pattern, number);
+            for (fi, f) in florbs.iter().enumerate() {
+                let mark = format!("{{{}}}", fi + 1);
+                let text = capture.get(fi + 1).map_or("", |text| text.as_str());
+                match *f {
+                    "{A}" | "{N}" | "{X}" => {
+                        replace_pattern = replace_pattern.replace(&mark, text);
+                    }
+                    "{D}" => {
+                        let date_capture = IOS_DATE_FORMAT_REGEX
+                            .captures(text)
+                            .ok_or_else(|| format!("Pattern error: {:?} is not a date", text))?;
+                        // The date regex only matches digits, so the numbers
+                        // can only be too large to parse.
+                        let number = |name: &str| {
+                            date_capture[name].parse::<u32>().map_err(|_| {
+                                format!("Pattern error: {:?} is not a valid date", text)
+                            })
+                        };
+                        let content = format!(
+                            "{:02}-{}-{:02}",
+                            number("y")?,
+                            month_to_number(&date_capture["m"])?,
+                            number("d")?
+                        );
+                        replace_pattern = replace_pattern.replace(&mark, &content);
+                    }
+                    _ => unreachable!("lowercase placeholders are rejected above"),
+                }
+            }
+            Ok(replace_pattern)
+        }
+    }
+}
+
+/// Translates the match pattern of a pattern match into the regular expression
+/// that the file names are matched against.
+pub fn pattern_match_regex(match_pattern: &str) -> Result<Regex, regex::Error> {
     let mut match_pattern = String::from(match_pattern);
     match_pattern.insert(0, '^');
     match_pattern.push('$');
@@ -526,245 +1064,98 @@ fn apply_pattern_match(
     let match_pattern = match_pattern.replace("{X}", r"(.*)"); // Anything
     let date_regex = r"((?:\d{1,2})\s(?i:January|February|March|April|May|June|July|August|September|October|November|December)\s(?:\d{1,4}))";
     let match_pattern = match_pattern.replace("{D}", date_regex); // Date
-                                                                  // println!("match pattern after replacement: {:?}", match_pattern);
-
-    // TODO Replace data generators
-    // n = n.replace("{date}",      time.strftime("%Y-%m-%d", time.localtime()))
-    // n = n.replace("{year}",      time.strftime("%Y",       time.localtime()))
-    // n = n.replace("{month}",     time.strftime("%m",       time.localtime()))
-    // n = n.replace("{monthname}", time.strftime("%B",       time.localtime()))
-    // n = n.replace("{monthsimp}", time.strftime("%b",       time.localtime()))
-    // n = n.replace("{day}",       time.strftime("%d",       time.localtime()))
-    // n = n.replace("{dayname}",   time.strftime("%A",       time.localtime()))
-    // n = n.replace("{daysimp}",   time.strftime("%a",       time.localtime()))
-
-    // TODO Replace random number generators
-    // # Replace {rand} with random number between 0 and 100.
-    // # If {rand500} the number will be between 0 and 500
-    // # If {rand10-20} the number will be between 10 and 20
-    // # If you add ,[ 5 the number will be padded with 5 digits
-    // # ie. {rand20,5} will be a number between 0 and 20 of 5 digits (00012)
-    // rnd = ""
-    // cr = re.compile("{(rand)([0-9]*)}"
-    //                 "|{(rand)([0-9]*)(\-)([0-9]*)}"
-    //                 "|{(rand)([0-9]*)(\,)([0-9]*)}"
-    //                 "|{(rand)([0-9]*)(\-)([0-9]*)(\,)([0-9]*)}")
-    // cg = cr.search(newname).groups()
-    // if len(cg) == 16:
-    //     if (cg[0] == "rand"):
-    //         if (cg[1] == ""):
-    //             # {rand}
-    //             rnd = random.randint(0, 100)
-    //         else:
-    //             # {rand2}
-    //             rnd = random.randint(0, int(cg[1]))
-    //     elif rand_case_1(cg):
-    //         # {rand10-100}
-    //         rnd = random.randint(int(cg[3]), int(cg[5]))
-    //     elif rand_case_2(cg):
-    //         if (cg[7] == ""):
-    //             # {rand,2}
-    //             rnd = str(random.randint(0, 100)).zfill(int(cg[9]))
-    //         else:
-    //             # {rand10,2}
-    //             rnd = str(random.randint(0, int(cg[7]))).zfill(int(cg[9]))
-    //     elif rand_case_3(cg):
-    //         # {rand2-10,3}
-    //         s = str(random.randint(int(cg[11]), int(cg[13])))
-    //         rnd = s.zfill(int(cg[15]))
-    // newname = cr.sub(str(rnd), newname)
-
-    // TODO Replace sequential number generators
-    // # Replace {num} with item number.
-    // # If {num2} the number will be 02
-    // # If {num3+10} the number will be 010
-    // count = str(count)
-    // cr = re.compile("{(num)([0-9]*)}|{(num)([0-9]*)(\+)([0-9]*)}")
-    // cg = cr.search(newname).groups()
-    // if len(cg) == 6:
-    //     if cg[0] == "num":
-    //         # {num2}
-    //         if cg[1] != "":
-    //             count = count.zfill(int(cg[1]))
-    //         newname = cr.sub(count, newname)
-    //     elif cg[2] == "num" and cg[4] == "+":
-    //         # {num2+5}
-    //         if cg[5] != "":
-    //             count = str(int(count)+int(cg[5]))
-    //         if cg[3] != "":
-    //             count = count.zfill(int(cg[3]))
-    // newname = cr.sub(count, newname)
-
-    let match_regex = Regex::new(&match_pattern).unwrap();
-    match match_regex.captures(&filename) {
-        None => {
-            println!("No match on {:?}", filename);
-            String::from(filename)
-        }
-        Some(capture) => {
-            let mut replace_pattern = replace_pattern.to_string();
-            let mut ci = 1;
-            for (fi, f) in florbs.iter().enumerate() {
-                let mark = format!("{{{}}}", fi + 1);
-                match *f {
-                    "{A}" | "{N}" | "{X}" => {
-                        let content = capture.get(ci).unwrap().as_str();
-                        replace_pattern = replace_pattern.replace(&mark, &content);
-                        ci += 1;
-                    }
-                    "{D}" => {
-                        lazy_static! {
-                            // This regex recognizes human-readable dates and its subparts
-                            static ref IOS_DATE_FORMAT_REGEX: Regex = Regex::new(r"(?i)(?P<d>\d{1,2})\s(?P<m>January|February|March|April|May|June|July|August|September|October|November|December)\s(?P<y>\d{1,4})").unwrap();
-                        }
-                        // println!("  capture: {:?}", capture);
-                        // println!("  ci: {}", ci);
-                        let date_text = capture.get(ci).unwrap().as_str();
-                        // println!("  date_text: {:?}", date_text);
-                        let date_capture = IOS_DATE_FORMAT_REGEX.captures(date_text).unwrap();
-                        // println!("  date_capture: {:?}", date_capture);
-                        let day_text = format!(
-                            "{:02}",
-                            date_capture
-                                .name("d")
-                                .unwrap()
-                                .as_str()
-                                .parse::<u32>()
-                                .unwrap()
-                        );
-                        let month_text = month_to_number(date_capture.name("m").unwrap().as_str());
-                        let year_text = format!(
-                            "{:02}",
-                            date_capture
-                                .name("y")
-                                .unwrap()
-                                .as_str()
-                                .parse::<u32>()
-                                .unwrap()
-                        );
-                        // let content = date_capture.get(ci).unwrap().as_str();
-                        let mut content = String::new();
-                        content.push_str(&year_text);
-                        content.push('-');
-                        content.push_str(&month_text);
-                        content.push('-');
-                        content.push_str(&day_text);
-                        // println!("  content: {:?}", content);
-                        replace_pattern = replace_pattern.replace(&mark, &content);
-                        ci += 1;
-                    }
-                    _ => {
-                        panic!("Unrecognized florb!");
-                    }
-                }
-            }
-            replace_pattern
-        }
+
+    Regex::new(&match_pattern)
+}
+
+/// Replaces the sequential number generators in a replace pattern:
+/// `{num}` is the number itself, `{num3}` pads it with zeros to three digits
+/// and `{num3+10}` adds ten to it before padding.
+fn apply_sequence_number(replace_pattern: &str, number: usize) -> String {
+    lazy_static! {
+        static ref NUM_REGEX: Regex = Regex::new(r"\{num(\d*)(?:\+(\d+))?\}").unwrap();
     }
+    NUM_REGEX
+        .replace_all(replace_pattern, |captures: &regex::Captures| {
+            let width = captures[1].parse::<usize>().unwrap_or(0);
+            let offset = captures
+                .get(2)
+                .map_or(0, |offset| offset.as_str().parse::<usize>().unwrap_or(0));
+            format!("{:0width$}", number + offset, width = width)
+        })
+        .into_owned()
 }
 
+/// Inserts `text` at `position`, or leaves the name alone if the anchor of the
+/// position isn't in it.
 fn apply_insert(filename: &str, text: &str, position: &Position) -> String {
     let mut new = String::from(filename);
-    match position {
-        Position::End => new.push_str(text),
-        Position::Index { value: index } if index >= &new.len() => new.push_str(text),
-        Position::Index { value: index } => new.insert_str(*index, text),
+    if let Some(index) = positions::resolve(filename, position) {
+        new.insert_str(index, text);
     }
     new
 }
 
-fn apply_interactive_tokenize(_filename: &str) -> String {
-    unimplemented!()
-}
-
-fn apply_interactive_pattern_match(_filename: &str) -> String {
-    unimplemented!()
-}
-
-fn apply_delete(filename: &str, from_idx: usize, to: &Position) -> String {
-    // This was the previous implementation:
-    // let mut filename2 = String::new();
-    // let filename1: Vec<char> = filename.chars().collect();
-    // for (idx, chr) in filename1.iter().enumerate() {
-    //     match to {
-    //         Position::End => {
-    //             if !(from <= idx) {
-    //                 filename2.push(*chr);
-    //             }
-    //         }
-    //         Position::Index { value } => {
-    //             if !((from <= idx) && (idx <= *value)) {
-    //                 filename2.push(*chr);
-    //             }
-    //         }
-    //     }
-    // }
-    // filename2
-    let to_idx = match *to {
-        Position::End => filename.len(),
-        Position::Index { value } => {
-            if value > filename.len() {
-                filename.len()
-            } else {
-                value
-            }
-        }
+fn apply_regex(filename: &str, regex: &Regex, replace: &str) -> String {
+    regex.replace_all(filename, replace).into_owned()
+}
+
+fn apply_tokenize(
+    filename: &str,
+    shape: &[WordKind],
+    order: &[usize],
+    separator: &str,
+    rest: bool,
+) -> String {
+    let ranges = crate::ocd::mrn::words::word_ranges(filename);
+    let words: Vec<&str> = ranges
+        .iter()
+        .map(|range| &filename[range.clone()])
+        .collect();
+    let kinds = crate::ocd::mrn::words::shape(&words);
+    let matches = match (rest, shape.split_last()) {
+        (true, Some((_, fixed))) => kinds.len() >= shape.len() && kinds.starts_with(fixed),
+        _ => kinds == shape,
     };
-    let mut s = String::from(filename);
-    s.replace_range(from_idx..to_idx, "");
-    s
-}
-
-fn create_undo_script(config: &MassRenameConfig, buffer: &BTreeMap<PathBuf, PathBuf>) {
-    if !config.verbosity.is_silent() {
-        println!("Creating undo script.");
-        match File::create("./undo.sh") {
-            Ok(mut output_file) => {
-                for (src, dst) in buffer {
-                    let result = if config.git {
-                        writeln!(output_file, "git mv {:?} {:?}", dst, src)
-                    } else {
-                        writeln!(output_file, "mv -i {:?} {:?}", dst, src)
-                    };
-                    if let Err(reason) = result {
-                        eprintln!("Error writing to undo file: {:?}", reason);
-                    }
-                }
-            }
-            Err(reason) => {
-                eprintln!("Error creating undo file: {:?}", reason);
-            }
-        }
+    if !matches {
+        return String::from(filename);
     }
+    let last = shape.len().saturating_sub(1);
+    let token = |index: usize| {
+        if rest && index == last {
+            // The rest runs from the start of its first word to the end of
+            // the name's last word, separators included.
+            &filename[ranges[last].start..ranges[ranges.len() - 1].end]
+        } else {
+            words[index]
+        }
+    };
+    order
+        .iter()
+        .map(|index| token(*index))
+        .collect::<Vec<&str>>()
+        .join(separator)
 }
 
-fn execute_rules(
-    config: &MassRenameConfig,
-    buffer: &BTreeMap<PathBuf, PathBuf>,
-) -> Result<(), String> {
-    for (src, dst) in buffer {
-        crate::ocd::output::file_move(config.verbosity, src, dst);
-        if !config.dryrun {
-            if config.git {
-                let src = src.to_str().unwrap();
-                let dst = dst.to_str().unwrap();
-                let _output = Command::new("git")
-                    .args(&["mv", src, dst])
-                    .output()
-                    .expect("Error invoking git.");
-            // TODO: do something with output
-            } else {
-                match fs::rename(src, dst) {
-                    Ok(_) => {}
-                    Err(reason) => {
-                        eprintln!("Error moving file: {:?}", reason);
-                        return Err(String::from("Error moving file."));
-                    }
-                }
-            }
-        }
+/// Deletes from `from` up to `to`, or leaves the name alone if the anchor of
+/// either position isn't in it.
+fn apply_delete(filename: &str, from: &Position, to: &Position) -> Result<String, String> {
+    let (from_index, to_index) = match (
+        positions::resolve(filename, from),
+        positions::resolve(filename, to),
+    ) {
+        (Some(from_index), Some(to_index)) => (from_index, to_index),
+        _ => return Ok(String::from(filename)),
+    };
+    if from_index > to_index {
+        return Err(format!(
+            "Delete error: {} is after {} in {:?}",
+            from, to, filename
+        ));
     }
-    Ok(())
+    let mut new = String::from(filename);
+    new.replace_range(from_index..to_index, "");
+    Ok(new)
 }
 
 fn new_buffer(files: &[PathBuf]) -> BTreeMap<PathBuf, PathBuf> {
@@ -775,7 +1166,8 @@ fn new_buffer(files: &[PathBuf]) -> BTreeMap<PathBuf, PathBuf> {
     buffer
 }
 
-fn clean_buffer(dirty_buffer: BTreeMap<PathBuf, PathBuf>) -> BTreeMap<PathBuf, PathBuf> {
+/// The entries of the buffer that the rules rename.
+fn clean_buffer(dirty_buffer: &BTreeMap<PathBuf, PathBuf>) -> BTreeMap<PathBuf, PathBuf> {
     let mut buffer = BTreeMap::new();
     for (src, dst) in dirty_buffer.iter().filter(|(src, dst)| src != dst) {
         buffer.insert(src.clone(), dst.clone());
@@ -783,29 +1175,40 @@ fn clean_buffer(dirty_buffer: BTreeMap<PathBuf, PathBuf>) -> BTreeMap<PathBuf, P
     buffer
 }
 
-fn rename_file(path: &mut PathBuf, filename: String) {
-    let extension = match path.extension() {
-        None => String::new(),
-        Some(extension) => String::from(extension.to_str().unwrap()),
-    };
-    path.set_file_name(filename);
-    path.set_extension(extension);
-}
-
 #[cfg(test)]
 mod test {
-    // use crate::ocd::mrn::apply_camel_case_join;
-    // use crate::ocd::mrn::apply_camel_case_split;
+    use crate::ocd::config::Verbosity;
+    use crate::ocd::error::OcdError;
+    use crate::ocd::mrn::apply_camel_case;
+    use crate::ocd::mrn::apply_camel_case_join;
+    use crate::ocd::mrn::apply_camel_case_split;
     use crate::ocd::mrn::apply_delete;
+    use crate::ocd::mrn::apply_dot_case;
     use crate::ocd::mrn::apply_insert;
+    use crate::ocd::mrn::apply_kebab_case;
     use crate::ocd::mrn::apply_lower_case;
+    use crate::ocd::mrn::apply_pascal_case;
     use crate::ocd::mrn::apply_pattern_match;
+    use crate::ocd::mrn::apply_regex;
     use crate::ocd::mrn::apply_replace;
     use crate::ocd::mrn::apply_sanitize;
+    use crate::ocd::mrn::apply_screaming_snake_case;
     use crate::ocd::mrn::apply_sentence_case;
+    use crate::ocd::mrn::apply_snake_case;
     use crate::ocd::mrn::apply_title_case;
+    use crate::ocd::mrn::apply_tokenize;
     use crate::ocd::mrn::apply_upper_case;
-    use crate::ocd::mrn::Position;
+    use crate::ocd::mrn::cleanup::Class;
+    use crate::ocd::mrn::conditions::Condition;
+    use crate::ocd::mrn::normalization::Form;
+    use crate::ocd::mrn::words::WordKind;
+    use crate::ocd::mrn::{
+        apply_rules, check_normalization, clean_buffer, new_buffer, pattern_match_regex,
+        sequence_numbers, Anchor, MassRenameConfig, Position, Rule, RuleRegex,
+    };
+    use regex::Regex;
+    use std::fs;
+    use std::path::{Path, PathBuf};
 
     macro_rules! t {
         ($t:ident : $s1:expr => $s2:expr) => {
@@ -816,13 +1219,6 @@ mod test {
         };
     }
 
-    // t!(test3: "MixedUP CamelCase, with some Spaces" => "Mixed Up Camel Case With Some Spaces");
-    // t!(test4: "mixed_up_ snake_case, with some _spaces" => "Mixed Up Snake Case With Some Spaces");
-    // t!(test5: "kebab-case" => "Kebab Case");
-    // t!(test6: "SHOUTY_SNAKE_CASE" => "Shouty Snake Case");
-    // t!(test7: "snake_case" => "Snake Case");
-    // t!(test8: "this-contains_ ALLKinds OfWord_Boundaries" => "This Contains All Kinds Of Word Boundaries");
-
     t!(lower_case_test:
         apply_lower_case("LoWeRcAsE") => "lowercase");
     t!(upper_case_test:
@@ -839,14 +1235,56 @@ mod test {
         apply_sentence_case("A SENTENCE HAS MULTIPLE WORDS") => "A sentence has multiple words");
     t!(sentence_case_test_4:
         apply_sentence_case("A sEnTeNcE HaS mUlTiPlE wOrDs") => "A sentence has multiple words");
-    // t!(camel_case_join_test:
-    //     apply_camel_case_join("Camel case Join") => "CamelCaseJoin");
-    // t!(camel_case_split_test_1:
-    //     apply_camel_case_split("CamelCase") => "Camel Case");
-    // t!(camel_case_split_test_2:
-    //     apply_camel_case_split("CamelCaseSplit") => "Camel Case Split");
-    // t!(camel_case_split_test_3:
-    //     apply_camel_case_split("XMLHttpRequest") => "Xml Http Request");
+    t!(camel_case_join_test_1:
+        apply_camel_case_join("Camel case Join") => "CamelCaseJoin");
+    t!(camel_case_join_test_2:
+        apply_camel_case_join("mixed_up-snake_case XMLHttp") => "MixedUpSnakeCaseXmlHttp");
+    t!(camel_case_split_test_1:
+        apply_camel_case_split("CamelCase") => "Camel Case");
+    t!(camel_case_split_test_2:
+        apply_camel_case_split("CamelCaseSplit") => "Camel Case Split");
+    t!(camel_case_split_test_3:
+        apply_camel_case_split("XMLHttpRequest") => "Xml Http Request");
+    t!(camel_case_split_test_4:
+        apply_camel_case_split("MixedUP CamelCase, with some Spaces") => "Mixed Up Camel Case With Some Spaces");
+    t!(camel_case_split_test_5:
+        apply_camel_case_split("mixed_up_ snake_case, with some _spaces") => "Mixed Up Snake Case With Some Spaces");
+    t!(camel_case_split_test_6:
+        apply_camel_case_split("kebab-case") => "Kebab Case");
+    t!(camel_case_split_test_7:
+        apply_camel_case_split("SHOUTY_SNAKE_CASE") => "Shouty Snake Case");
+    t!(camel_case_split_test_8:
+        apply_camel_case_split("snake_case") => "Snake Case");
+    t!(camel_case_split_test_9:
+        apply_camel_case_split("this-contains_ ALLKinds OfWord_Boundaries") => "This Contains All Kinds Of Word Boundaries");
+    t!(camel_case_split_test_10:
+        apply_camel_case_split("ÉcoleDeMúsica01Intro") => "École De Música01 Intro");
+    t!(snake_case_test_1:
+        apply_snake_case("Snake Case") => "snake_case");
+    t!(snake_case_test_2:
+        apply_snake_case("XMLHttpRequest, with-dashes") => "xml_http_request_with_dashes");
+    t!(kebab_case_test_1:
+        apply_kebab_case("Kebab Case") => "kebab-case");
+    t!(kebab_case_test_2:
+        apply_kebab_case("mixed_UP camelCase") => "mixed-up-camel-case");
+    t!(screaming_snake_case_test_1:
+        apply_screaming_snake_case("screaming snake case") => "SCREAMING_SNAKE_CASE");
+    t!(screaming_snake_case_test_2:
+        apply_screaming_snake_case("straßeNummer-3") => "STRASSE_NUMMER_3");
+    t!(pascal_case_test_1:
+        apply_pascal_case("pascal_case") => "PascalCase");
+    t!(pascal_case_test_2:
+        apply_pascal_case("kebab-case XMLHttp") => "KebabCaseXmlHttp");
+    t!(camel_case_test_1:
+        apply_camel_case("Camel Case") => "camelCase");
+    t!(camel_case_test_2:
+        apply_camel_case("SHOUTY_SNAKE_CASE") => "shoutySnakeCase");
+    t!(camel_case_test_3:
+        apply_camel_case(" _ ") => "");
+    t!(dot_case_test_1:
+        apply_dot_case("Dot Case") => "dot.case");
+    t!(dot_case_test_2:
+        apply_dot_case("this-contains_ ALLKinds OfWord_Boundaries") => "this.contains.all.kinds.of.word.boundaries");
     t!(replace_test:
         apply_replace("aa bbccdd ee", "cc", "ff") => "aa bbffdd ee");
     t!(replace_space_dash_test:
@@ -874,17 +1312,33 @@ mod test {
     t!(replace_under_space_test:
         apply_replace("aa_bb_cc_dd", "_", " ") => "aa bb cc dd");
     t!(pattern_match_test_1:
-        apply_pattern_match(0, "aa bb", "{X} {X}", "{2} {1}") => "bb aa");
+        apply_pattern_match(0, "aa bb", "{X} {X}", "{2} {1}").unwrap() => "bb aa");
     t!(pattern_match_test_2:
-        apply_pattern_match(0, "Dave Brubeck - 01. Take five", "{X} - {N}. {X}", "{1} {2} {3}") => "Dave Brubeck 01 Take five");
+        apply_pattern_match(0, "Dave Brubeck - 01. Take five", "{X} - {N}. {X}", "{1} {2} {3}").unwrap() => "Dave Brubeck 01 Take five");
     t!(pattern_match_test_3:
-        apply_pattern_match(0, "Bahia Blanca, 21 October 2019", "{X}, {D}", "{1} {2}") => "Bahia Blanca 2019-10-21");
+        apply_pattern_match(0, "Bahia Blanca, 21 October 2019", "{X}, {D}", "{1} {2}").unwrap() => "Bahia Blanca 2019-10-21");
     t!(pattern_match_test_4:
-        apply_pattern_match(0, "Foo 123 B_a_r", "{A} {N} {X}", "{3} {2} {1}") => "B_a_r 123 Foo");
+        apply_pattern_match(0, "Foo 123 B_a_r", "{A} {N} {X}", "{3} {2} {1}").unwrap() => "B_a_r 123 Foo");
     t!(pattern_match_test_5:
-        apply_pattern_match(0, "Bahia Blanca, 21 October 2019", "{X}, {D}", "{2} {1}") => "2019-10-21 Bahia Blanca");
+        apply_pattern_match(0, "Bahia Blanca, 21 October 2019", "{X}, {D}", "{2} {1}").unwrap() => "2019-10-21 Bahia Blanca");
     t!(pattern_match_test_6:
-        apply_pattern_match(0, "Bahia Blanca, 21 October 2019, FooBarBaz", "{X}, {D}, {X}", "{2} {1} {3}") => "2019-10-21 Bahia Blanca FooBarBaz");
+        apply_pattern_match(0, "Bahia Blanca, 21 October 2019, FooBarBaz", "{X}, {D}, {X}", "{2} {1} {3}").unwrap() => "2019-10-21 Bahia Blanca FooBarBaz");
+    t!(regex_test_1:
+        apply_regex("01 - Take five", &Regex::new(r"^(\d+) - (.*)$").unwrap(), "$2 ($1)") => "Take five (01)");
+    t!(regex_test_2:
+        apply_regex("IMG_1234", &Regex::new(r"IMG_(?P<number>\d+)").unwrap(), "photo-${number}") => "photo-1234");
+    t!(regex_test_3:
+        apply_regex("a.b.c", &Regex::new(r"\.").unwrap(), " ") => "a b c");
+    t!(regex_test_4:
+        apply_regex("Track TRACK track", &Regex::new(r"(?i)track").unwrap(), "Song") => "Song Song Song");
+    t!(pattern_match_test_7:
+        apply_pattern_match(7, "Take five", "{X}", "{num} {1}").unwrap() => "7 Take five");
+    t!(pattern_match_test_8:
+        apply_pattern_match(7, "Take five", "{X}", "{num3} {1}").unwrap() => "007 Take five");
+    t!(pattern_match_test_9:
+        apply_pattern_match(7, "Take five", "{X}", "{num3+10} {1}").unwrap() => "017 Take five");
+    t!(pattern_match_test_10:
+        apply_pattern_match(7, "{num}", "{X}", "{1} {num2}").unwrap() => "{num} 07");
     t!(insert_test_1:
         apply_insert("aa bb", " cc", &Position::End) => "aa bb cc");
     t!(insert_test_2:
@@ -894,9 +1348,367 @@ mod test {
     t!(sanitize_test:
         apply_sanitize("04 Three village scenes_ Lakodalom [BB 87_B]") => "04 Three village scenes Lakodalom BB 87 B");
     t!(delete_test_1:
-        apply_delete("aa bb cc", 0, &Position::End) => "");
+        apply_delete("aa bb cc", &Position::Index { value: 0 }, &Position::End).unwrap() => "");
     t!(delete_test_2:
-        apply_delete("aa bb cc", 0, &Position::Index { value: 3 }) => "bb cc");
+        apply_delete("aa bb cc", &Position::Index { value: 0 }, &Position::Index { value: 3 }).unwrap() => "bb cc");
     t!(delete_test_3:
-        apply_delete("aa bb cc", 0, &Position::Index { value: 42 }) => "");
+        apply_delete("aa bb cc", &Position::Index { value: 0 }, &Position::Index { value: 42 }).unwrap() => "");
+    t!(delete_test_4:
+        apply_delete("Beyoncé – Halo 👍🏽", &Position::FromEnd { value: 2 }, &Position::End).unwrap() => "Beyoncé – Halo");
+    t!(delete_test_5:
+        apply_delete("Track 03 Halo", &Position::Index { value: 0 }, &Position::Before { anchor: Anchor::Digit }).unwrap() => "03 Halo");
+    t!(insert_test_4:
+        apply_insert("Beyonce\u{301} Halo", " –", &Position::Index { value: 7 }) => "Beyonce\u{301} – Halo");
+    t!(delete_test_6:
+        apply_delete("Artist - Title - Live", &Position::After { anchor: Anchor::Text(String::from(" - ")) }, &Position::End).unwrap() => "Artist - ");
+    t!(insert_test_6:
+        apply_insert("Album 1999", " (remaster)", &Position::After { anchor: Anchor::Regex(RuleRegex(Regex::new(r"\d{4}$").unwrap())) }) => "Album 1999 (remaster)");
+    t!(insert_test_5:
+        apply_insert("Halo", " 01", &Position::Before { anchor: Anchor::Digit }) => "Halo");
+
+    #[test]
+    fn delete_error_test() {
+        let from = Position::FromEnd { value: 2 };
+        assert!(apply_delete("abc", &from, &Position::Index { value: 0 }).is_err());
+        assert_eq!(
+            apply_delete("abc", &from, &Position::Index { value: 2 }),
+            Ok(String::from("ac"))
+        );
+    }
+
+    #[test]
+    fn sequence_numbers_test() {
+        let files: Vec<PathBuf> = ["b/2", "a/2", "b/1", "a/1", "a/3"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let buffer = new_buffer(&files);
+        let mut config = MassRenameConfig::new();
+        config.num_start = 10;
+        config.num_step = 5;
+        assert_eq!(sequence_numbers(&config, &buffer), vec![10, 15, 20, 25, 30]);
+        config.num_reset = true;
+        assert_eq!(sequence_numbers(&config, &buffer), vec![10, 15, 20, 10, 15]);
+    }
+
+    #[test]
+    fn random_collision_test() {
+        let files: Vec<PathBuf> = ["a.txt", "b.txt"].iter().map(PathBuf::from).collect();
+        let mut config = MassRenameConfig::new();
+        config.verbosity = Verbosity::Silent;
+        config.seed = Some(1);
+        let rules = [Rule::PatternMatch {
+            pattern: String::from("{X}"),
+            replace: String::from("{rand1}"),
+        }];
+        let buffer = apply_rules(&config, &rules, &files).unwrap();
+        let mut names: Vec<&PathBuf> = buffer.values().collect();
+        names.sort();
+        assert_eq!(names, [&PathBuf::from("0.txt"), &PathBuf::from("1.txt")]);
+        assert_eq!(apply_rules(&config, &rules, &files).unwrap(), buffer);
+
+        let files: Vec<PathBuf> = ["a.txt", "b.txt", "c.txt"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        assert!(apply_rules(&config, &rules, &files).is_err());
+    }
+
+    #[test]
+    fn pattern_match_error_test() {
+        assert_eq!(
+            apply_pattern_match(0, "Concert, 21 OCTOBER 2019", "{X}, {D}", "{2} {1}"),
+            Ok(String::from("2019-10-21 Concert"))
+        );
+        assert!(apply_pattern_match(0, "aa bb", "{x} {X}", "{2} {1}").is_err());
+        assert!(apply_pattern_match(0, "aa bb", "{X} {a}", "{2} {1}").is_err());
+    }
+
+    #[test]
+    fn rule_error_test() {
+        let files = [PathBuf::from("a.txt")];
+        let mut config = MassRenameConfig::new();
+        config.verbosity = Verbosity::Silent;
+        let rules = [Rule::PatternMatch {
+            pattern: String::from("{X}"),
+            replace: String::from("{year:%Y}"),
+        }];
+        match apply_rules(&config, &rules, &files) {
+            Err(error @ OcdError::Rule { .. }) => {
+                assert_eq!(error.exit_code(), 4);
+                assert!(error.to_string().contains("\"a.txt\""));
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn pattern_match_date_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<PathBuf> = ["IMG_1.jpg", "notes.txt"]
+            .iter()
+            .map(|name| dir.path().join(name))
+            .collect();
+        for file in &files {
+            fs::write(file, "").unwrap();
+        }
+        let mut config = MassRenameConfig::new();
+        config.verbosity = Verbosity::Silent;
+        let rules = [Rule::PatternMatch {
+            pattern: String::from("IMG_{N}"),
+            replace: String::from("{exif} {1}"),
+        }];
+        // Only the matching file needs an EXIF date.
+        assert!(apply_rules(&config, &rules, &files).is_err());
+        let names = apply_rules(&config, &rules, &files[1..]).unwrap();
+        assert_eq!(names.values().collect::<Vec<&PathBuf>>(), [&files[1]]);
+    }
+
+    #[test]
+    fn conditional_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<PathBuf> = ["a b.mp3", "c d.txt", "e f"]
+            .iter()
+            .map(|name| dir.path().join(name))
+            .collect();
+        fs::write(&files[0], "").unwrap();
+        fs::write(&files[1], "").unwrap();
+        fs::create_dir(&files[2]).unwrap();
+        let mut config = MassRenameConfig::new();
+        config.verbosity = Verbosity::Silent;
+        let rules = [Rule::Conditional {
+            condition: Condition::Extension(String::from("mp3")),
+            then: vec![Rule::TitleCase, Rule::ReplaceSpaceDash],
+            otherwise: vec![Rule::Conditional {
+                condition: Condition::Directory,
+                then: vec![Rule::UpperCase],
+                otherwise: vec![],
+            }],
+        }];
+        let buffer = apply_rules(&config, &rules, &files).unwrap();
+        let names: Vec<&Path> = buffer
+            .values()
+            .map(|path| path.strip_prefix(dir.path()).unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            [Path::new("A-B.mp3"), Path::new("c d.txt"), Path::new("E F")]
+        );
+    }
+
+    #[test]
+    fn extension_rules_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<PathBuf> = ["A.JPEG", "b.Tiff", "c"]
+            .iter()
+            .map(|name| dir.path().join(name))
+            .collect();
+        fs::write(&files[0], "").unwrap();
+        fs::write(&files[1], "").unwrap();
+        fs::write(&files[2], [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]).unwrap();
+        let mut config = MassRenameConfig::new();
+        config.verbosity = Verbosity::Silent;
+        let rules = [
+            Rule::LowerCase,
+            Rule::ExtensionLowerCase,
+            Rule::ExtensionMap {
+                from: String::from("jpeg"),
+                to: String::from("jpg"),
+            },
+            Rule::ExtensionMap {
+                from: String::from("TIFF"),
+                to: String::from("tif"),
+            },
+            Rule::ExtensionDetect,
+        ];
+        let buffer = apply_rules(&config, &rules, &files).unwrap();
+        let names: Vec<&Path> = buffer
+            .values()
+            .map(|path| path.strip_prefix(dir.path()).unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            [Path::new("a.jpg"), Path::new("b.tif"), Path::new("c.png")]
+        );
+    }
+
+    #[test]
+    fn compound_extension_test() {
+        let files: Vec<PathBuf> = ["my.backup.TAR.GZ", "a b.txt", "types.d.ts"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let mut config = MassRenameConfig::new();
+        config.verbosity = Verbosity::Silent;
+        let rules = [
+            Rule::ReplacePeriodSpace,
+            Rule::TitleCase,
+            Rule::ReplaceSpacePeriod,
+            Rule::ExtensionLowerCase,
+            Rule::ExtensionMap {
+                from: String::from("tar.gz"),
+                to: String::from("tgz"),
+            },
+        ];
+        let buffer = apply_rules(&config, &rules, &files).unwrap();
+        assert_eq!(
+            buffer.values().collect::<Vec<&PathBuf>>(),
+            [
+                &PathBuf::from("A.B.txt"),
+                &PathBuf::from("My.Backup.tgz"),
+                &PathBuf::from("Types.d.ts")
+            ]
+        );
+    }
+
+    #[test]
+    fn cleanup_test() {
+        let files = vec![
+            PathBuf::from(" Artist - Title (Live)!.mp3"),
+            PathBuf::from("_Notes..  .txt"),
+        ];
+        let mut config = MassRenameConfig::new();
+        config.verbosity = Verbosity::Silent;
+        let rules = [
+            Rule::Trim {
+                class: Class::Space,
+            },
+            Rule::ReplaceSpaceDash,
+            Rule::Collapse { class: Class::Dash },
+            Rule::Trim {
+                class: Class::Punctuation,
+            },
+            Rule::Collapse {
+                class: Class::Separator,
+            },
+        ];
+        let buffer = apply_rules(&config, &rules, &files).unwrap();
+        assert_eq!(
+            buffer.values().collect::<Vec<&PathBuf>>(),
+            [
+                &PathBuf::from("Artist-Title-(Live).mp3"),
+                &PathBuf::from("Notes.txt")
+            ]
+        );
+    }
+
+    #[test]
+    fn normalize_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = vec![
+            dir.path().join("Cafe\u{301}.mp3"),
+            dir.path().join("Caf\u{e9}.wav"),
+        ];
+        for file in &files {
+            fs::write(file, "").unwrap();
+        }
+        let mut config = MassRenameConfig::new();
+        config.verbosity = Verbosity::Silent;
+        let names = apply_rules(
+            &config,
+            &[Rule::Normalize { form: Form::Nfc }, Rule::ExtensionRemove],
+            &files,
+        )
+        .unwrap();
+        assert_eq!(
+            names.values().collect::<Vec<&PathBuf>>(),
+            [&dir.path().join("Caf\u{e9}"), &dir.path().join("Caf\u{e9}")]
+        );
+        assert!(crate::ocd::mrn::engine::plan(&clean_buffer(&names)).is_err());
+
+        let names = apply_rules(&config, &[Rule::ExtensionRemove], &files).unwrap();
+        match check_normalization(&config, &names) {
+            Err(OcdError::Normalization { dst, .. }) => {
+                assert_eq!(dst, dir.path().join("Caf\u{e9}"))
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+        let names = apply_rules(&config, &[Rule::LowerCase], &files).unwrap();
+        assert!(check_normalization(&config, &names).is_ok());
+    }
+
+    #[test]
+    fn normalize_unchanged_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = vec![
+            dir.path().join("beyonce\u{301}"),
+            dir.path().join("beyonc\u{e9}"),
+        ];
+        for file in &files {
+            fs::create_dir(file).unwrap();
+        }
+        let mut config = MassRenameConfig::new();
+        config.verbosity = Verbosity::Silent;
+        // Neither name changes, and both are already a problem.
+        let names = apply_rules(&config, &[Rule::LowerCase], &files[..1]).unwrap();
+        assert!(clean_buffer(&names).is_empty());
+        assert!(matches!(
+            check_normalization(&config, &names),
+            Err(OcdError::Normalization { .. })
+        ));
+
+        // A new name that only differs from an entry left alone.
+        let other = dir.path().join("other");
+        fs::remove_dir(&files[0]).unwrap();
+        fs::create_dir(&other).unwrap();
+        let rules = [Rule::Replace {
+            pattern: String::from("other"),
+            replace: String::from("beyonce\u{301}"),
+        }];
+        let names = apply_rules(&config, &rules, std::slice::from_ref(&other)).unwrap();
+        match check_normalization(&config, &names) {
+            Err(OcdError::Normalization {
+                src, other: first, ..
+            }) => {
+                assert_eq!((first, src), (files[1].clone(), other));
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn pattern_match_regex_test() {
+        let regex = pattern_match_regex("{X} - {N}. (live)").unwrap();
+        assert!(regex.is_match("Dave Brubeck - 01. (live)"));
+        assert!(!regex.is_match("Dave Brubeck - 01. live"));
+        assert!(pattern_match_regex("{x} - {X}").is_err());
+    }
+
+    #[test]
+    fn tokenize_test() {
+        let shape = [WordKind::Letters, WordKind::Letters];
+        assert_eq!(
+            apply_tokenize("Artist - Title", &shape, &[1, 0], " - ", false),
+            "Title - Artist"
+        );
+        assert_eq!(
+            apply_tokenize("artist_title", &shape, &[1], "", false),
+            "title"
+        );
+        assert_eq!(
+            apply_tokenize("Artist - Title 2", &shape, &[1, 0], " - ", false),
+            "Artist - Title 2"
+        );
+    }
+
+    #[test]
+    fn tokenize_rest_test() {
+        // Sampled from "Help - Lennon", whose last token takes the rest.
+        let shape = [WordKind::Letters, WordKind::Letters];
+        let tokenize = |name| apply_tokenize(name, &shape, &[1, 0], " - ", true);
+        assert_eq!(tokenize("Help - Lennon"), "Lennon - Help");
+        assert_eq!(
+            tokenize("Yesterday - Paul McCartney"),
+            "Paul McCartney - Yesterday"
+        );
+        assert_eq!(tokenize("Yesterday - 1965 Live"), "1965 Live - Yesterday");
+        assert_eq!(tokenize("Yesterday"), "Yesterday");
+        assert_eq!(tokenize("1965 - Yesterday"), "1965 - Yesterday");
+        // Without the rest, the camel case split of McCartney makes the name
+        // one token too long.
+        assert_eq!(
+            apply_tokenize("Help - McCartney", &shape, &[1, 0], " - ", false),
+            "Help - McCartney"
+        );
+    }
 }
@@ -0,0 +1,75 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use unicode_normalization::UnicodeNormalization;
+
+/// The Unicode normalization forms that the `nfc` and `nfd` rules convert
+/// names to.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Form {
+    /// Composed, e.g. é as a single character, as most systems write it.
+    Nfc,
+    /// Decomposed, e.g. é as an e followed by a combining accent, as macOS
+    /// filesystems used to store it.
+    Nfd,
+}
+
+pub fn apply(filename: &str, form: Form) -> String {
+    match form {
+        Form::Nfc => filename.nfc().collect(),
+        Form::Nfd => filename.nfd().collect(),
+    }
+}
+
+/// The entries of the buffer whose new names only differ in their Unicode
+/// normalization, grouped by the name they share once composed. Entries moved
+/// to the very same name are left to the planner to report.
+pub fn collisions(buffer: &BTreeMap<PathBuf, PathBuf>) -> Vec<Vec<(&PathBuf, &PathBuf)>> {
+    let mut groups: BTreeMap<String, Vec<(&PathBuf, &PathBuf)>> = BTreeMap::new();
+    for (src, dst) in buffer {
+        groups
+            .entry(apply(&dst.to_string_lossy(), Form::Nfc))
+            .or_default()
+            .push((src, dst));
+    }
+    groups
+        .into_values()
+        .filter(|group| group.iter().any(|(_, dst)| *dst != group[0].1))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const COMPOSED: &str = "Caf\u{e9}";
+    const DECOMPOSED: &str = "Cafe\u{301}";
+
+    #[test]
+    fn apply_test() {
+        assert_eq!(apply(DECOMPOSED, Form::Nfc), COMPOSED);
+        assert_eq!(apply(COMPOSED, Form::Nfd), DECOMPOSED);
+        assert_eq!(apply("plain", Form::Nfd), "plain");
+    }
+
+    #[test]
+    fn collisions_test() {
+        let mut buffer = BTreeMap::new();
+        buffer.insert(PathBuf::from("a"), PathBuf::from(COMPOSED));
+        buffer.insert(PathBuf::from("b"), PathBuf::from(DECOMPOSED));
+        buffer.insert(PathBuf::from("c"), PathBuf::from("dir/Cafe"));
+        buffer.insert(
+            PathBuf::from("d"),
+            PathBuf::from(format!("dir/{}", COMPOSED)),
+        );
+        assert_eq!(
+            collisions(&buffer),
+            vec![vec![
+                (&PathBuf::from("a"), &PathBuf::from(COMPOSED)),
+                (&PathBuf::from("b"), &PathBuf::from(DECOMPOSED)),
+            ]]
+        );
+
+        buffer.remove(&PathBuf::from("b"));
+        assert!(collisions(&buffer).is_empty());
+    }
+}
@@ -1,420 +1,354 @@
-use crate::ocd::mrn::lexer::Token;
-use crate::ocd::mrn::{Position, Rule};
+use crate::ocd::error::OcdError;
+use crate::ocd::mrn::conditions::{self, Condition};
+use crate::ocd::mrn::diagnostic::Diagnostic;
+use crate::ocd::mrn::keywords::{self, Arguments, Kind, Value};
+use crate::ocd::mrn::lexer::{Token, Tokens};
+use crate::ocd::mrn::{Anchor, Position, Rule, RuleRegex};
+use regex::Regex;
+
+const IF_USAGE: &str = "usage: if <condition> { <rules> } else { <rules> }";
+
+/// A syntax error at the first of the `remaining` tokens, or right after the
+/// last token if there are none left.
+struct SyntaxError {
+    message: String,
+    remaining: usize,
+    hint: Option<String>,
+}
+
+impl SyntaxError {
+    /// An error at the first of `tokens`.
+    fn at(tokens: &[Token], message: &str) -> SyntaxError {
+        SyntaxError {
+            message: String::from(message),
+            remaining: tokens.len(),
+            hint: None,
+        }
+    }
+
+    /// An error at the token right before `tokens`.
+    fn before(tokens: &[Token], message: &str) -> SyntaxError {
+        SyntaxError {
+            message: String::from(message),
+            remaining: tokens.len() + 1,
+            hint: None,
+        }
+    }
+
+    fn with_hint(mut self, hint: &str) -> SyntaxError {
+        self.hint.get_or_insert_with(|| String::from(hint));
+        self
+    }
+}
 
 pub fn parse(
     _config: &crate::ocd::mrn::MassRenameConfig,
-    tokens: &[crate::ocd::mrn::lexer::Token],
-) -> Result<Vec<Rule>, String> {
+    tokens: &Tokens,
+) -> Result<Vec<Rule>, OcdError> {
+    let kinds: Vec<Token> = tokens
+        .lexemes
+        .iter()
+        .map(|lexeme| lexeme.token.clone())
+        .collect();
     let mut rules = Vec::new();
-    match tokens.len() {
-        0 => Ok(rules),
-        1 => {
-            parse_rules(&tokens[0], &[], &mut rules)?;
-            Ok(rules)
-        }
-        2 => Err(String::from("Error: unexpected token")),
-        _ => {
-            parse_rules(&tokens[0], &tokens[1..], &mut rules)?;
-            Ok(rules)
+    if kinds.is_empty() {
+        return Ok(rules);
+    }
+    match parse_rules(&kinds, &mut rules) {
+        Ok(()) => Ok(rules),
+        Err(error) => {
+            let (offset, length) = match tokens.lexemes.get(kinds.len() - error.remaining) {
+                Some(lexeme) => (lexeme.offset, lexeme.length),
+                None => (tokens.input.chars().count(), 1),
+            };
+            let diagnostic = Diagnostic::new(&tokens.input, offset, length).with_hint(error.hint);
+            Err(OcdError::Parse {
+                message: error.message,
+                diagnostic: Some(diagnostic),
+            })
         }
     }
 }
 
-fn parse_rules<'a, 'b>(
-    token: &Token,
-    tokens: &'a [Token],
-    rules: &'b mut Vec<Rule>,
-) -> Result<&'a [Token], String> {
-    let tokens = parse_rule(token, tokens, rules)?;
-    match tokens.len() {
-        0 => Ok(tokens),
-        1 => Err(String::from("Syntax error: unexpected token")),
-        _ => match tokens[0] {
-            Token::Comma => {
-                let tokens = parse_rules(&tokens[1], &tokens[2..], rules)?;
-                Ok(tokens)
-            }
-            _ => Err(String::from("Syntax error: unexpected token")),
-        },
+fn parse_rules(tokens: &[Token], rules: &mut Vec<Rule>) -> Result<(), SyntaxError> {
+    let tokens = parse_sequence(tokens, rules)?;
+    match tokens.first() {
+        None => Ok(()),
+        _ => Err(
+            SyntaxError::at(skip_space(tokens), "Syntax error: unexpected }")
+                .with_hint("there is no block to close"),
+        ),
     }
 }
 
-fn parse_rule<'a, 'b>(
-    token: &Token,
+/// Reads rules separated by commas, each optionally followed by a space, up
+/// to the end of the rules or of the block they are in.
+fn parse_sequence<'a>(
     tokens: &'a [Token],
-    rules: &'b mut Vec<Rule>,
-) -> Result<&'a [Token], String> {
-    match *token {
-        Token::Comma => return Err(String::from("Syntax error: unexpected comma")),
-        Token::Space => return Err(String::from("Syntax error: unexpected space")),
-        Token::End => return Err(String::from("Syntax error: unexpected end keyword")),
-        Token::String { value: ref _value } => {
-            return Err(String::from("Syntax error: unexpected string"))
-        }
-        Token::Number { value: _value } => {
-            return Err(String::from("Syntax error: unexpected number"))
-        }
-        Token::LowerCase => {
-            rules.push(Rule::LowerCase);
-        }
-        Token::UpperCase => {
-            rules.push(Rule::UpperCase);
-        }
-        Token::TitleCase => {
-            rules.push(Rule::TitleCase);
-        }
-        Token::SentenceCase => {
-            rules.push(Rule::SentenceCase);
-        }
-        Token::CamelCaseJoin => {
-            rules.push(Rule::CamelCaseJoin);
-        }
-        Token::CamelCaseSplit => {
-            rules.push(Rule::CamelCaseSplit);
-        }
-        Token::ExtensionAdd => match tokens.len() {
-            0 => {
-                return Err(String::from(
-                    "Syntax error: insufficient parameters for extension add",
-                ))
+    rules: &mut Vec<Rule>,
+) -> Result<&'a [Token], SyntaxError> {
+    let mut tokens = parse_rule(tokens, rules)?;
+    loop {
+        tokens = match tokens {
+            [] | [Token::CloseBrace, ..] | [Token::Space, Token::CloseBrace, ..] => {
+                return Ok(tokens)
+            }
+            [Token::Comma, Token::Space, rest @ ..] | [Token::Comma, rest @ ..] => {
+                parse_rule(rest, rules)?
             }
             _ => {
-                let tokens = parse_extension_add(&tokens[0], &tokens[1..], rules)?;
-                return Ok(tokens);
+                return Err(SyntaxError::at(tokens, "Syntax error: unexpected token")
+                    .with_hint("separate rules with a comma"))
             }
-        },
-        Token::ExtensionRemove => {
-            rules.push(Rule::ExtensionRemove);
         }
-        Token::PatternMatch => {
-            if tokens.is_empty() {
-                return Err(String::from(
-                    "Syntax error: insufficient parameters for pattern match",
-                ));
-            } else {
-                let tokens = parse_pattern_match(&tokens[0], &tokens[1..], rules)?;
-                return Ok(tokens);
-            }
+    }
+}
+
+fn parse_rule<'a>(tokens: &'a [Token], rules: &mut Vec<Rule>) -> Result<&'a [Token], SyntaxError> {
+    let (token, tokens) = match tokens.split_first() {
+        Some((Token::If, tokens)) => return parse_conditional(tokens, rules),
+        Some(split) => split,
+        None => return Err(SyntaxError::at(tokens, "Syntax error: expected a rule")),
+    };
+    let keyword = match keywords::of(token) {
+        Some(keyword) => keyword,
+        None => {
+            return Err(SyntaxError::before(
+                tokens,
+                &format!("Syntax error: unexpected {}", describe(token)),
+            ))
         }
-        Token::Insert => {
-            if tokens.is_empty() {
-                return Err(String::from(
-                    "Syntax error: insufficient parameters for insert",
-                ));
-            } else {
-                let tokens = parse_insert(&tokens[0], &tokens[1..], rules)?;
-                return Ok(tokens);
+    };
+    let usage = format!("usage: {}", keyword.usage());
+    let mut tokens = tokens;
+    let mut values = Vec::new();
+    // How many tokens were left at each argument, to point at it.
+    let mut remaining = Vec::new();
+    for argument in keyword.arguments {
+        tokens = space(
+            tokens,
+            &format!(
+                "Syntax error: {} expected a space before <{}>",
+                keyword.name, argument.name
+            ),
+        )
+        .map_err(|error| error.with_hint(&usage))?;
+        remaining.push(tokens.len());
+        let value = match (argument.kind, tokens) {
+            (Kind::String, [Token::String { value }, rest @ ..]) => {
+                Some((Value::String(value.clone()), rest))
             }
-        }
-        Token::InteractiveTokenize => {
-            rules.push(Rule::InteractiveTokenize);
-        }
-        Token::InteractivePatternMatch => {
-            rules.push(Rule::InteractivePatternMatch);
-        }
-        Token::Delete => {
-            if tokens.is_empty() {
-                return Err(String::from(
-                    "Syntax error: insufficient parameters for delete",
-                ));
-            } else {
-                let tokens = parse_delete(&tokens[0], &tokens[1..], rules)?;
-                return Ok(tokens);
+            (Kind::Position, _) => {
+                position(tokens)?.map(|(position, rest)| (Value::Position(position), rest))
             }
-        }
-        Token::Replace => {
-            if tokens.is_empty() {
-                return Err(String::from(
-                    "Syntax error: insufficient parameters for replace",
-                ));
-            } else {
-                let tokens = parse_replace(&tokens[0], &tokens[1..], rules)?;
-                return Ok(tokens);
+            _ => None,
+        };
+        let (value, rest) = match value {
+            Some(value) => value,
+            None => {
+                return Err(SyntaxError::at(
+                    tokens,
+                    &format!(
+                        "Syntax error: {} expected {} for <{}>",
+                        keyword.name,
+                        argument.kind.description(),
+                        argument.name
+                    ),
+                )
+                .with_hint(&usage))
             }
-        }
-        Token::Sanitize => {
-            rules.push(Rule::Sanitize);
-        }
-        Token::ReplaceSpaceDash => {
-            rules.push(Rule::ReplaceSpaceDash);
-        }
-        Token::ReplaceSpacePeriod => {
-            rules.push(Rule::ReplaceSpacePeriod);
-        }
-        Token::ReplaceSpaceUnder => {
-            rules.push(Rule::ReplaceSpaceUnder);
-        }
-        Token::ReplaceDashPeriod => {
-            rules.push(Rule::ReplaceDashPeriod);
-        }
-        Token::ReplaceDashSpace => {
-            rules.push(Rule::ReplaceDashSpace);
-        }
-        Token::ReplaceDashUnder => {
-            rules.push(Rule::ReplaceDashUnder);
-        }
-        Token::ReplacePeriodDash => {
-            rules.push(Rule::ReplacePeriodDash);
-        }
-        Token::ReplacePeriodSpace => {
-            rules.push(Rule::ReplacePeriodSpace);
-        }
-        Token::ReplacePeriodUnder => {
-            rules.push(Rule::ReplacePeriodUnder);
-        }
-        Token::ReplaceUnderDash => {
-            rules.push(Rule::ReplaceUnderDash);
-        }
-        Token::ReplaceUnderPeriod => {
-            rules.push(Rule::ReplaceUnderPeriod);
-        }
-        Token::ReplaceUnderSpace => {
-            rules.push(Rule::ReplaceUnderSpace);
-        }
+        };
+        values.push(value);
+        tokens = rest;
     }
+    let mut arguments = Arguments::new(values);
+    let rule = (keyword.rule)(&mut arguments).map_err(|message| SyntaxError {
+        message,
+        remaining: remaining
+            .get(arguments.taken.saturating_sub(1))
+            .copied()
+            .unwrap_or(tokens.len()),
+        hint: None,
+    })?;
+    rules.push(rule);
     Ok(tokens)
 }
 
-fn parse_pattern_match<'a, 'b>(
-    token: &Token,
+/// Reads a conditional rule, after its if keyword.
+fn parse_conditional<'a>(
     tokens: &'a [Token],
-    rules: &'b mut Vec<Rule>,
-) -> Result<&'a [Token], String> {
-    match token {
-        Token::Space => {
-            if tokens.is_empty() {
-                Err(String::from("Syntax error: pattern match expected a space"))
-            } else {
-                match &tokens[0] {
-                    Token::String {
-                        value: ref match_pattern,
-                    } => {
-                        let tokens = &tokens[1..];
-                        if tokens.is_empty() {
-                            Err(String::from(
-                                "Syntax error: pattern match expected a space after the pattern",
-                            ))
-                        } else {
-                            match &tokens[0] {
-                                Token::Space => {
-                                    let tokens = &tokens[1..];
-                                    if tokens.is_empty() {
-                                        Err(String::from(
-                                            "Synatx error: pattern match expected a second string",
-                                        ))
-                                    } else {
-                                        match &tokens[0] {
-                      Token::String{value: ref replace_pattern} => {
-                        let mp = match_pattern.to_string();
-                        let rp = replace_pattern.to_string();
-                        rules.push(Rule::PatternMatch{pattern: mp, replace: rp});
-                        Ok(&tokens[1..])
-                      },
-                      _ => Err(String::from("Syntax error: pattern match expected a second string"))
-                    }
-                                    }
-                                }
-                                _ => Err(String::from(
-                                    "Syntax error: pattern match expected a space between patterns",
-                                )),
-                            }
-                        }
-                    }
-                    _ => Err(String::from("Syntax error: pattern expected string")),
-                }
-            }
-        }
-        _ => Err(String::from("Syntax error: expected space")),
+    rules: &mut Vec<Rule>,
+) -> Result<&'a [Token], SyntaxError> {
+    let tokens = space(
+        tokens,
+        "Syntax error: if expected a space before <condition>",
+    )
+    .map_err(|error| error.with_hint(IF_USAGE))?;
+    let (condition, tokens) = parse_condition(tokens)?;
+    let (then, mut tokens) = parse_block(skip_space(tokens))?;
+    let mut otherwise = Vec::new();
+    if let [Token::Else, rest @ ..] = skip_space(tokens) {
+        let (rules, rest) = parse_block(skip_space(rest))?;
+        otherwise = rules;
+        tokens = rest;
     }
+    rules.push(Rule::Conditional {
+        condition,
+        then,
+        otherwise,
+    });
+    Ok(tokens)
 }
 
-fn parse_extension_add<'a, 'b>(
-    token: &Token,
-    tokens: &'a [Token],
-    rules: &'b mut Vec<Rule>,
-) -> Result<&'a [Token], String> {
-    match token {
-        Token::Space => {
-            if tokens.is_empty() {
-                Err(String::from(
-                    "Syntax error: extensinon add expected a string",
-                ))
-            } else {
-                match tokens[0] {
-                    Token::String {
-                        value: ref extension,
-                    } => {
-                        let extension = extension.to_string();
-                        rules.push(Rule::ExtensionAdd { extension });
-                        Ok(&tokens[1..])
-                    }
-                    _ => Err(String::from(
-                        "Syntax error: extension add expected a string",
-                    )),
-                }
-            }
+/// Reads a condition, optionally negated with a `!`.
+fn parse_condition(tokens: &[Token]) -> Result<(Condition, &[Token]), SyntaxError> {
+    let condition = match tokens.split_first() {
+        Some((Token::Not, rest)) => {
+            let (condition, rest) = parse_condition(rest)?;
+            return Ok((Condition::Not(Box::new(condition)), rest));
         }
-        _ => Err(String::from("Syntax error: extension add expected a space")),
+        Some((Token::Condition { value }, _)) => Condition::parse(value),
+        Some((Token::RegexLiteral { value }, _)) => Regex::new(value)
+            .map(|regex| Condition::Name(RuleRegex(regex)))
+            .map_err(|err| format!("invalid regex {:?}\n{}", value, err)),
+        _ => Err(String::from("if expected a condition")),
+    };
+    match condition {
+        Ok(condition) => Ok((condition, &tokens[1..])),
+        Err(message) => Err(
+            SyntaxError::at(tokens, &format!("Syntax error: {}", message))
+                .with_hint(conditions::USAGE),
+        ),
     }
 }
 
-fn parse_insert<'a, 'b>(
-    token: &Token,
-    tokens: &'a [Token],
-    rules: &'b mut Vec<Rule>,
-) -> Result<&'a [Token], String> {
-    match token {
-        Token::Space => {
-            if tokens.is_empty() {
-                Err(String::from("Syntax error: insert expected a string"))
-            } else {
-                match tokens[0] {
-                    Token::String { value: ref text } => {
-                        let tokens = &tokens[1..];
-                        if tokens.is_empty() {
-                            Err(String::from("Syntax error: insert expected a space"))
-                        } else {
-                            match tokens[0] {
-                                Token::Space => {
-                                    let tokens = &tokens[1..];
-                                    if tokens.is_empty() {
-                                        Err(String::from(
-                                            "Syntax error: insert expected an index or end keyword",
-                                        ))
-                                    } else {
-                                        match &tokens[0] {
-                      Token::End => {
-                        rules.push(Rule::Insert{text: text.to_string(), position: Position::End});
-                        Ok(&tokens[1..])
-                      },
-                      &Token::Number{value: position} => {
-                        rules.push(Rule::Insert{text: text.to_string(), position: Position::Index{value: position}});
-                        Ok(&tokens[1..])
-                      },
-                      _ => Err(String::from("Syntax error: insert expected an index or end keyword"))
-                    }
-                                    }
-                                }
-                                _ => Err(String::from("Syntax error: inssert expected a space")),
-                            }
-                        }
-                    }
-                    _ => Err(String::from("Syntax error: insert expected a string")),
-                }
-            }
+/// Reads the rules between braces, which may be surrounded by spaces.
+fn parse_block(tokens: &[Token]) -> Result<(Vec<Rule>, &[Token]), SyntaxError> {
+    let start = tokens;
+    let tokens = match tokens.split_first() {
+        Some((Token::OpenBrace, rest)) => skip_space(rest),
+        _ => return Err(SyntaxError::at(tokens, "Syntax error: expected a {").with_hint(IF_USAGE)),
+    };
+    let mut rules = Vec::new();
+    let tokens = match tokens.first() {
+        Some(Token::CloseBrace) => tokens,
+        _ => skip_space(parse_sequence(tokens, &mut rules)?),
+    };
+    let tokens = match tokens.split_first() {
+        Some((Token::CloseBrace, rest)) => rest,
+        _ => {
+            return Err(SyntaxError::at(tokens, "Syntax error: unclosed block")
+                .with_hint("close the block with a '}'"))
         }
-        _ => Err(String::from("Syntax error: insert expected a space")),
+    };
+    // The interactive rules apply to every name at once, so they can't be
+    // limited to some of them.
+    if rules.iter().any(|rule| {
+        matches!(
+            rule,
+            Rule::InteractivePatternMatch | Rule::InteractiveTokenize
+        )
+    }) {
+        return Err(SyntaxError::at(
+            start,
+            "Syntax error: interactive rules can't be used in a conditional",
+        ));
     }
+    Ok((rules, tokens))
 }
 
-fn parse_delete<'a, 'b>(
-    token: &Token,
-    tokens: &'a [Token],
-    rules: &'b mut Vec<Rule>,
-) -> Result<&'a [Token], String> {
+/// Names a token that does not start a rule, for error messages.
+fn describe(token: &Token) -> &'static str {
     match token {
-        Token::Space => {
-            if tokens.is_empty() {
-                Err(String::from(
-                    "Syntax error: delete expected an index number",
+        Token::Comma => "comma",
+        Token::Space => "space",
+        Token::OpenBrace => "{",
+        Token::CloseBrace => "}",
+        Token::Not => "!",
+        Token::Else => "else without an if",
+        Token::Condition { .. } => "condition without an if",
+        Token::RegexLiteral { .. } => "regex",
+        Token::End => "end keyword",
+        Token::String { .. } => "string",
+        Token::Number { .. } => "number",
+        _ => "token",
+    }
+}
+
+/// Reads a position: an index from the start or the end, the start or end
+/// keywords, or before or after an anchor. Returns None if the tokens aren't
+/// a position.
+fn position(tokens: &[Token]) -> Result<Option<(Position, &[Token])>, SyntaxError> {
+    let position = match tokens {
+        [Token::Number { value }, rest @ ..] => (Position::Index { value: *value }, rest),
+        [Token::NegativeNumber { value }, rest @ ..] => (Position::FromEnd { value: *value }, rest),
+        [Token::Start, rest @ ..] => (Position::Start, rest),
+        [Token::End, rest @ ..] => (Position::End, rest),
+        [Token::Before, Token::Space, rest @ ..] => match anchor(rest)? {
+            Some((anchor, rest)) => (Position::Before { anchor }, rest),
+            None => return Ok(None),
+        },
+        [Token::After, Token::Space, rest @ ..] => match anchor(rest)? {
+            Some((anchor, rest)) => (Position::After { anchor }, rest),
+            None => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(position))
+}
+
+/// Reads what a position is relative to: a digit, a letter, a string or a
+/// regex between slashes.
+fn anchor(tokens: &[Token]) -> Result<Option<(Anchor, &[Token])>, SyntaxError> {
+    let (token, rest) = match tokens.split_first() {
+        Some(split) => split,
+        None => return Ok(None),
+    };
+    let anchor = match token {
+        Token::Digit => Anchor::Digit,
+        Token::Letter => Anchor::Letter,
+        Token::String { value } => Anchor::Text(value.clone()),
+        Token::RegexLiteral { value } => match Regex::new(value) {
+            Ok(regex) => Anchor::Regex(RuleRegex(regex)),
+            Err(reason) => {
+                return Err(SyntaxError::at(
+                    tokens,
+                    &format!("Syntax error: invalid regex {:?}\n{}", value, reason),
                 ))
-            } else {
-                match tokens[0] {
-                    Token::Number { value: from } => {
-                        let tokens = &tokens[1..];
-                        if tokens.is_empty() {
-                            Err(String::from("Syntax error: delete expected a space"))
-                        } else {
-                            match tokens[0] {
-                                Token::Space => {
-                                    let tokens = &tokens[1..];
-                                    if tokens.is_empty() {
-                                        Err(String::from(
-                                            "Syntax error: delete expected a position",
-                                        ))
-                                    } else {
-                                        match &tokens[0] {
-                      Token::End => {
-                        rules.push(Rule::Delete{from, to: Position::End});
-                        Ok(&tokens[1..])
-                      },
-                      &Token::Number{value: to} => {
-                        rules.push(Rule::Delete{from, to: Position::Index{value: to}});
-                        Ok(&tokens[1..])
-                      },
-                      _ => Err(String::from("Syntax error: delete expected either end of an index number"))
-                    }
-                                    }
-                                }
-                                _ => Err(String::from("Syntax error: delete expected a space")),
-                            }
-                        }
-                    }
-                    _ => Err(String::from(
-                        "Syntax error: delete expected an index number",
-                    )),
-                }
             }
-        }
-        _ => Err(String::from("Syntax error: delete expected a space")),
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some((anchor, rest)))
+}
+
+/// Moves past an optional space.
+fn skip_space(tokens: &[Token]) -> &[Token] {
+    match tokens.split_first() {
+        Some((Token::Space, tokens)) => tokens,
+        _ => tokens,
     }
 }
 
-fn parse_replace<'a, 'b>(
-    token: &Token,
-    tokens: &'a [Token],
-    rules: &'b mut Vec<Rule>,
-) -> Result<&'a [Token], String> {
-    match token {
-        Token::Space => {
-            if tokens.is_empty() {
-                Err(String::from("Syntax error: replace expected a string"))
-            } else {
-                match tokens[0] {
-                    Token::String { value: ref string1 } => {
-                        let tokens = &tokens[1..];
-                        if tokens.is_empty() {
-                            Err(String::from("Syntax error: replace expected a space"))
-                        } else {
-                            match tokens[0] {
-                                Token::Space => {
-                                    let tokens = &tokens[1..];
-                                    if tokens.is_empty() {
-                                        Err(String::from(
-                                            "Syntax error: replace expected a second string",
-                                        ))
-                                    } else {
-                                        match tokens[0] {
-                                            Token::String { value: ref string2 } => {
-                                                let pattern = string1.to_string();
-                                                let replace = string2.to_string();
-                                                rules.push(Rule::Replace { pattern, replace });
-                                                Ok(&tokens[1..])
-                                            }
-                                            _ => Err(String::from(
-                                                "Syntax error: replace expected a second string",
-                                            )),
-                                        }
-                                    }
-                                }
-                                _ => Err(String::from("Syntax error: replace expected a space")),
-                            }
-                        }
-                    }
-                    _ => Err(String::from("Syntax error: replace expected a string")),
-                }
-            }
-        }
-        _ => Err(String::from("Syntax error: replace expected a space")),
+/// Reads the space separating the arguments of a rule.
+fn space<'a>(tokens: &'a [Token], message: &str) -> Result<&'a [Token], SyntaxError> {
+    match tokens.split_first() {
+        Some((Token::Space, tokens)) => Ok(tokens),
+        _ => Err(SyntaxError::at(tokens, message)),
     }
 }
 
 #[cfg(test)]
 mod test {
+    use crate::ocd::error::OcdError;
+    use crate::ocd::mrn::cleanup::Class;
+    use crate::ocd::mrn::conditions::Condition;
     use crate::ocd::mrn::lexer::tokenize;
+    use crate::ocd::mrn::normalization::Form;
     use crate::ocd::mrn::parser::parse;
+    use crate::ocd::mrn::sanitize::Profile;
     use crate::ocd::mrn::MassRenameConfig;
-    use crate::ocd::mrn::{Position, Rule};
+    use crate::ocd::mrn::{Anchor, Position, Rule};
 
     #[test]
     fn empty_test() {
@@ -422,7 +356,7 @@ mod test {
         let empty: [Rule; 0] = [];
         assert_eq!(
             &empty,
-            parse(&config, &tokenize(&config, &"").unwrap())
+            parse(&config, &tokenize(&config, "").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -433,7 +367,7 @@ mod test {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::LowerCase],
-            parse(&config, &tokenize(&config, &"lc").unwrap())
+            parse(&config, &tokenize(&config, "lc").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -444,7 +378,7 @@ mod test {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::UpperCase],
-            parse(&config, &tokenize(&config, &"uc").unwrap())
+            parse(&config, &tokenize(&config, "uc").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -455,7 +389,7 @@ mod test {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::TitleCase],
-            parse(&config, &tokenize(&config, &"tc").unwrap())
+            parse(&config, &tokenize(&config, "tc").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -466,7 +400,7 @@ mod test {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::SentenceCase],
-            parse(&config, &tokenize(&config, &"sc").unwrap())
+            parse(&config, &tokenize(&config, "sc").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -477,7 +411,7 @@ mod test {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::CamelCaseJoin],
-            parse(&config, &tokenize(&config, &"ccj").unwrap())
+            parse(&config, &tokenize(&config, "ccj").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -488,29 +422,241 @@ mod test {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::CamelCaseSplit],
-            parse(&config, &tokenize(&config, &"ccs").unwrap())
+            parse(&config, &tokenize(&config, "ccs").unwrap())
                 .unwrap()
                 .as_slice()
         );
     }
 
+    #[test]
+    fn identifier_case_test() {
+        let config = MassRenameConfig::new();
+        assert_eq!(
+            &[
+                Rule::SnakeCase,
+                Rule::KebabCase,
+                Rule::ScreamingSnakeCase,
+                Rule::PascalCase,
+                Rule::CamelCase,
+                Rule::DotCase,
+            ],
+            parse(&config, &tokenize(&config, "snc,kc,ssc,pc,cc,dc").unwrap())
+                .unwrap()
+                .as_slice()
+        );
+    }
+
+    #[test]
+    fn regex_test() {
+        let config = MassRenameConfig::new();
+        let rules = parse(
+            &config,
+            &tokenize(&config, r#"x "^(\d+)" "$1 -",xi "mp3" "MP3""#).unwrap(),
+        )
+        .unwrap();
+        match rules.as_slice() {
+            [Rule::Regex {
+                regex: first,
+                replace: first_replace,
+            }, Rule::Regex {
+                regex: second,
+                replace: second_replace,
+            }] => {
+                assert_eq!(first.0.as_str(), r"^(\d+)");
+                assert_eq!(first_replace, "$1 -");
+                assert!(!first.0.is_match("a1"));
+                assert_eq!(second.0.as_str(), "(?i)mp3");
+                assert!(second.0.is_match("Mp3"));
+                assert_eq!(second_replace, "MP3");
+            }
+            rules => panic!("unexpected rules {:?}", rules),
+        }
+    }
+
+    #[test]
+    fn invalid_regex_test() {
+        let config = MassRenameConfig::new();
+        assert!(parse(&config, &tokenize(&config, r#"x "(\d+" "$1""#).unwrap()).is_err());
+        assert!(parse(&config, &tokenize(&config, r#"x "\d+""#).unwrap()).is_err());
+        assert!(parse(&config, &tokenize(&config, "x").unwrap()).is_err());
+    }
+
+    #[test]
+    fn error_position_test() {
+        let config = MassRenameConfig::new();
+        let position = |rules: &str| match parse(&config, &tokenize(&config, rules).unwrap()) {
+            Err(OcdError::Parse {
+                diagnostic: Some(diagnostic),
+                ..
+            }) => (diagnostic.offset, diagnostic.length, diagnostic.hint),
+            result => panic!("unexpected result {:?}", result),
+        };
+        assert_eq!(
+            position("lc uc"),
+            (2, 1, Some(String::from("separate rules with a comma")))
+        );
+        assert_eq!(
+            position("lc,r \"a\""),
+            (8, 1, Some(String::from("usage: r \"<match>\" \"<text>\"")))
+        );
+        assert_eq!(position("d 1 \"x\"").0, 4);
+        assert_eq!(position("lc,,uc").0, 3);
+        assert_eq!(position(r#"x "(\d+" "$1""#).0, 2);
+    }
+
+    #[test]
+    fn conditional_test() {
+        let config = MassRenameConfig::new();
+        assert_eq!(
+            &[
+                Rule::LowerCase,
+                Rule::Conditional {
+                    condition: Condition::Extension(String::from("mp3")),
+                    then: vec![Rule::TitleCase, Rule::ReplaceSpaceDash],
+                    otherwise: vec![Rule::Conditional {
+                        condition: Condition::Not(Box::new(Condition::Directory)),
+                        then: vec![],
+                        otherwise: vec![Rule::UpperCase],
+                    }],
+                },
+                Rule::Sanitize,
+            ],
+            parse(
+                &config,
+                &tokenize(
+                    &config,
+                    "lc,if ext=mp3 { tc, sd } else {if !dir {} else {uc}},s"
+                )
+                .unwrap()
+            )
+            .unwrap()
+            .as_slice()
+        );
+        match parse(&config, &tokenize(&config, "if /^\\d+ / {uc}").unwrap())
+            .unwrap()
+            .as_slice()
+        {
+            [Rule::Conditional {
+                condition: Condition::Name(regex),
+                ..
+            }] => assert!(regex.0.is_match("01 a")),
+            rules => panic!("unexpected rules {:?}", rules),
+        }
+    }
+
+    #[test]
+    fn conditional_error_test() {
+        let config = MassRenameConfig::new();
+        let error = |rules: &str| match parse(&config, &tokenize(&config, rules).unwrap()) {
+            Err(OcdError::Parse {
+                message,
+                diagnostic: Some(diagnostic),
+            }) => (message, diagnostic.offset),
+            result => panic!("unexpected result {:?}", result),
+        };
+        assert_eq!(
+            error("if ext=mp3 {tc"),
+            (String::from("Syntax error: unclosed block"), 14)
+        );
+        assert_eq!(error("if size>1X {tc}").1, 3);
+        assert_eq!(
+            error("if size>99999999999G { lc }"),
+            (
+                String::from("Syntax error: size \"99999999999G\" is too large"),
+                3
+            )
+        );
+        assert_eq!(error("if /(/ {tc}").1, 3);
+        assert_eq!(error("if {tc}").1, 3);
+        assert_eq!(error("if dir tc").1, 7);
+        assert_eq!(error("lc}").1, 2);
+        assert_eq!(error("lc,else {uc}").1, 3);
+        assert_eq!(error("if dir {tc,ip}").1, 7);
+    }
+
     #[test]
     fn sanitize_test() {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::Sanitize],
-            parse(&config, &tokenize(&config, &"s").unwrap())
+            parse(&config, &tokenize(&config, "s").unwrap())
+                .unwrap()
+                .as_slice()
+        );
+    }
+
+    #[test]
+    fn sanitize_profile_test() {
+        let config = MassRenameConfig::new();
+        assert_eq!(
+            &[Rule::SanitizeProfile {
+                profile: Profile::Slug
+            }],
+            parse(&config, &tokenize(&config, "sa \"slug\"").unwrap())
+                .unwrap()
+                .as_slice()
+        );
+        match parse(&config, &tokenize(&config, "sa \"Ascii\"").unwrap()) {
+            Err(OcdError::Parse {
+                message,
+                diagnostic: Some(diagnostic),
+            }) => {
+                assert_eq!(
+                    message,
+                    "Syntax error: unknown sanitize profile \"Ascii\", did you mean `ascii`?"
+                );
+                assert_eq!(diagnostic.offset, 3);
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn normalize_test() {
+        let config = MassRenameConfig::new();
+        assert_eq!(
+            &[
+                Rule::Normalize { form: Form::Nfd },
+                Rule::Normalize { form: Form::Nfc }
+            ],
+            parse(&config, &tokenize(&config, "nfd,nfc").unwrap())
                 .unwrap()
                 .as_slice()
         );
     }
 
+    #[test]
+    fn cleanup_test() {
+        let config = MassRenameConfig::new();
+        assert_eq!(
+            &[
+                Rule::Trim {
+                    class: Class::Space
+                },
+                Rule::Trim {
+                    class: Class::Punctuation
+                },
+                Rule::Collapse { class: Class::Dash }
+            ],
+            parse(&config, &tokenize(&config, "tw,tp,cs \"dash\"").unwrap())
+                .unwrap()
+                .as_slice()
+        );
+        match parse(&config, &tokenize(&config, "cs \"dashes\"").unwrap()) {
+            Err(OcdError::Parse { message, .. }) => assert_eq!(
+                message,
+                "Syntax error: unknown character class \"dashes\", did you mean `dash`?"
+            ),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
     #[test]
     fn replace_space_dash_test() {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::ReplaceSpaceDash],
-            parse(&config, &tokenize(&config, &"sd").unwrap())
+            parse(&config, &tokenize(&config, "sd").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -521,7 +667,7 @@ mod test {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::ReplaceSpacePeriod],
-            parse(&config, &tokenize(&config, &"sp").unwrap())
+            parse(&config, &tokenize(&config, "sp").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -532,7 +678,7 @@ mod test {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::ReplaceSpaceUnder],
-            parse(&config, &tokenize(&config, &"su").unwrap())
+            parse(&config, &tokenize(&config, "su").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -543,7 +689,7 @@ mod test {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::ReplaceDashPeriod],
-            parse(&config, &tokenize(&config, &"dp").unwrap())
+            parse(&config, &tokenize(&config, "dp").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -554,7 +700,7 @@ mod test {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::ReplaceDashSpace],
-            parse(&config, &tokenize(&config, &"ds").unwrap())
+            parse(&config, &tokenize(&config, "ds").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -565,7 +711,7 @@ mod test {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::ReplaceDashUnder],
-            parse(&config, &tokenize(&config, &"du").unwrap())
+            parse(&config, &tokenize(&config, "du").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -576,7 +722,7 @@ mod test {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::ReplacePeriodDash],
-            parse(&config, &tokenize(&config, &"pd").unwrap())
+            parse(&config, &tokenize(&config, "pd").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -587,7 +733,7 @@ mod test {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::ReplacePeriodSpace],
-            parse(&config, &tokenize(&config, &"ps").unwrap())
+            parse(&config, &tokenize(&config, "ps").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -598,7 +744,7 @@ mod test {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::ReplacePeriodUnder],
-            parse(&config, &tokenize(&config, &"pu").unwrap())
+            parse(&config, &tokenize(&config, "pu").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -609,7 +755,7 @@ mod test {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::ReplaceUnderDash],
-            parse(&config, &tokenize(&config, &"ud").unwrap())
+            parse(&config, &tokenize(&config, "ud").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -620,7 +766,7 @@ mod test {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::ReplaceUnderPeriod],
-            parse(&config, &tokenize(&config, &"up").unwrap())
+            parse(&config, &tokenize(&config, "up").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -631,7 +777,7 @@ mod test {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::ReplaceUnderSpace],
-            parse(&config, &tokenize(&config, &"us").unwrap())
+            parse(&config, &tokenize(&config, "us").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -642,7 +788,7 @@ mod test {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::InteractiveTokenize],
-            parse(&config, &tokenize(&config, &"it").unwrap())
+            parse(&config, &tokenize(&config, "it").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -653,7 +799,7 @@ mod test {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::InteractivePatternMatch],
-            parse(&config, &tokenize(&config, &"ip").unwrap())
+            parse(&config, &tokenize(&config, "ip").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -667,7 +813,7 @@ mod test {
                 pattern: String::from("a"),
                 replace: String::from("b")
             }],
-            parse(&config, &tokenize(&config, &"p \"a\" \"b\"").unwrap())
+            parse(&config, &tokenize(&config, "p \"a\" \"b\"").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -680,18 +826,40 @@ mod test {
             &[Rule::ExtensionAdd {
                 extension: String::from("mp3")
             }],
-            parse(&config, &tokenize(&config, &"ea \"mp3\"").unwrap())
+            parse(&config, &tokenize(&config, "ea \"mp3\"").unwrap())
                 .unwrap()
                 .as_slice()
         );
     }
 
+    #[test]
+    fn extension_case_and_map_test() {
+        let config = MassRenameConfig::new();
+        assert_eq!(
+            &[
+                Rule::ExtensionLowerCase,
+                Rule::ExtensionUpperCase,
+                Rule::ExtensionMap {
+                    from: String::from("jpeg"),
+                    to: String::from("jpg")
+                },
+                Rule::ExtensionDetect,
+            ],
+            parse(
+                &config,
+                &tokenize(&config, "el,eu,em \"jpeg\" \"jpg\",ed").unwrap()
+            )
+            .unwrap()
+            .as_slice()
+        );
+    }
+
     #[test]
     fn extension_remove_test() {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::ExtensionRemove],
-            parse(&config, &tokenize(&config, &"er").unwrap())
+            parse(&config, &tokenize(&config, "er").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -705,7 +873,7 @@ mod test {
                 text: String::from("text"),
                 position: Position::End
             }],
-            parse(&config, &tokenize(&config, &"i \"text\" end").unwrap())
+            parse(&config, &tokenize(&config, "i \"text\" end").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -719,7 +887,7 @@ mod test {
                 text: String::from("text"),
                 position: Position::Index { value: 0 }
             }],
-            parse(&config, &tokenize(&config, &"i \"text\" 0").unwrap())
+            parse(&config, &tokenize(&config, "i \"text\" 0").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -733,7 +901,7 @@ mod test {
                 text: String::from("text"),
                 position: Position::Index { value: 5 }
             }],
-            parse(&config, &tokenize(&config, &"i \"text\" 5").unwrap())
+            parse(&config, &tokenize(&config, "i \"text\" 5").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -744,10 +912,10 @@ mod test {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::Delete {
-                from: 0,
+                from: Position::Index { value: 0 },
                 to: Position::End
             }],
-            parse(&config, &tokenize(&config, &"d 0 end").unwrap())
+            parse(&config, &tokenize(&config, "d 0 end").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -758,15 +926,85 @@ mod test {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::Delete {
-                from: 0,
+                from: Position::Index { value: 0 },
                 to: Position::Index { value: 10 }
             }],
-            parse(&config, &tokenize(&config, &"d 0 10").unwrap())
+            parse(&config, &tokenize(&config, "d 0 10").unwrap())
                 .unwrap()
                 .as_slice()
         );
     }
 
+    #[test]
+    fn relative_position_test() {
+        let config = MassRenameConfig::new();
+        assert_eq!(
+            &[
+                Rule::Delete {
+                    from: Position::FromEnd { value: 3 },
+                    to: Position::Before {
+                        anchor: Anchor::Digit
+                    }
+                },
+                Rule::Insert {
+                    text: String::from("-"),
+                    position: Position::After {
+                        anchor: Anchor::Letter
+                    }
+                },
+            ],
+            parse(
+                &config,
+                &tokenize(&config, "d -3 before digit,i \"-\" after letter").unwrap()
+            )
+            .unwrap()
+            .as_slice()
+        );
+        assert!(parse(&config, &tokenize(&config, "d 3 1").unwrap()).is_err());
+        assert!(parse(&config, &tokenize(&config, "d -1 -3").unwrap()).is_err());
+        assert!(parse(&config, &tokenize(&config, "d end 0").unwrap()).is_err());
+        assert!(parse(&config, &tokenize(&config, "d 1 start").unwrap()).is_err());
+        assert!(parse(&config, &tokenize(&config, "i \"a\" before /(/").unwrap()).is_err());
+    }
+
+    #[test]
+    fn text_anchor_test() {
+        let config = MassRenameConfig::new();
+        let rules = parse(
+            &config,
+            &tokenize(
+                &config,
+                "d after \" - \" end,i \" (remaster)\" before /\\d{4}$/,d start 2",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        match rules.as_slice() {
+            [Rule::Delete {
+                from:
+                    Position::After {
+                        anchor: Anchor::Text(text),
+                    },
+                to: Position::End,
+            }, Rule::Insert {
+                text: insert,
+                position:
+                    Position::Before {
+                        anchor: Anchor::Regex(regex),
+                    },
+            }, Rule::Delete {
+                from: Position::Start,
+                to: Position::Index { value: 2 },
+            }] => {
+                assert_eq!(text, " - ");
+                assert_eq!(insert, " (remaster)");
+                assert_eq!(regex.0.as_str(), "\\d{4}$");
+            }
+            rules => panic!("unexpected rules {:?}", rules),
+        }
+        assert!(parse(&config, &tokenize(&config, "i \"a\" before end").unwrap()).is_err());
+    }
+
     #[test]
     fn replace_test() {
         let config = MassRenameConfig::new();
@@ -775,7 +1013,7 @@ mod test {
                 pattern: String::from("text"),
                 replace: String::from("TEXT")
             }],
-            parse(&config, &tokenize(&config, &"r \"text\" \"TEXT\"").unwrap())
+            parse(&config, &tokenize(&config, "r \"text\" \"TEXT\"").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -786,7 +1024,7 @@ mod test {
         let config = MassRenameConfig::new();
         assert_eq!(
             &[Rule::Sanitize, Rule::InteractiveTokenize,],
-            parse(&config, &tokenize(&config, &"s,it").unwrap())
+            parse(&config, &tokenize(&config, "s,it").unwrap())
                 .unwrap()
                 .as_slice()
         );
@@ -807,7 +1045,7 @@ mod test {
             ],
             parse(
                 &config,
-                &tokenize(&config, &"p \"{#} - {X}\" \"{1}. {2}\",ds,ps,us").unwrap()
+                &tokenize(&config, "p \"{#} - {X}\" \"{1}. {2}\",ds,ps,us").unwrap()
             )
             .unwrap()
             .as_slice()
@@ -0,0 +1,111 @@
+use crate::ocd::mrn::{Anchor, Position};
+use std::iter;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The byte index in `name` that `position` refers to, always at the boundary
+/// of a grapheme cluster, or None if the anchor of the position isn't in the
+/// name. Indices past either end of the name stop at it.
+pub fn resolve(name: &str, position: &Position) -> Option<usize> {
+    let boundaries: Vec<usize> = name
+        .grapheme_indices(true)
+        .map(|(index, _)| index)
+        .chain(iter::once(name.len()))
+        .collect();
+    let count = boundaries.len() - 1;
+    match position {
+        Position::Start => Some(0),
+        Position::End => Some(name.len()),
+        Position::Index { value } => Some(boundaries[count.min(*value)]),
+        Position::FromEnd { value } => Some(boundaries[count.saturating_sub(*value)]),
+        Position::Before { anchor } => {
+            let (start, _) = find(name, anchor)?;
+            // A match that starts inside a grapheme starts before it.
+            boundaries
+                .iter()
+                .rev()
+                .find(|index| **index <= start)
+                .copied()
+        }
+        Position::After { anchor } => {
+            let (_, end) = find(name, anchor)?;
+            boundaries.iter().find(|index| **index >= end).copied()
+        }
+    }
+}
+
+/// Where the first occurrence of `anchor` in `name` starts and ends.
+fn find(name: &str, anchor: &Anchor) -> Option<(usize, usize)> {
+    let class = |is_class: fn(char) -> bool| {
+        name.grapheme_indices(true)
+            .find(|(_, grapheme)| grapheme.chars().next().is_some_and(is_class))
+            .map(|(index, grapheme)| (index, index + grapheme.len()))
+    };
+    match anchor {
+        Anchor::Digit => class(char::is_numeric),
+        Anchor::Letter => class(char::is_alphabetic),
+        Anchor::Text(text) => name
+            .find(text.as_str())
+            .map(|index| (index, index + text.len())),
+        Anchor::Regex(regex) => regex.0.find(name).map(|found| (found.start(), found.end())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ocd::mrn::RuleRegex;
+
+    #[test]
+    fn resolve_test() {
+        // The e and its combining accent are a single grapheme.
+        let name = "ca\u{0301}fe\u{0301} 👍🏽 02";
+        let at = |position: Position| resolve(name, &position).map(|index| &name[..index]);
+        assert_eq!(at(Position::Index { value: 0 }), Some(""));
+        assert_eq!(at(Position::Index { value: 2 }), Some("ca\u{0301}"));
+        assert_eq!(
+            at(Position::Index { value: 6 }),
+            Some("ca\u{0301}fe\u{0301} 👍🏽")
+        );
+        assert_eq!(at(Position::Index { value: 42 }), Some(name));
+        assert_eq!(
+            at(Position::FromEnd { value: 3 }),
+            Some("ca\u{0301}fe\u{0301} 👍🏽")
+        );
+        assert_eq!(at(Position::FromEnd { value: 42 }), Some(""));
+        assert_eq!(at(Position::End), Some(name));
+        assert_eq!(
+            at(Position::Before {
+                anchor: Anchor::Digit
+            }),
+            Some("ca\u{0301}fe\u{0301} 👍🏽 ")
+        );
+        assert_eq!(
+            at(Position::After {
+                anchor: Anchor::Letter
+            }),
+            Some("c")
+        );
+        assert_eq!(
+            at(Position::Before {
+                anchor: Anchor::Text(String::from("e"))
+            }),
+            Some("ca\u{0301}f")
+        );
+        assert_eq!(
+            at(Position::After {
+                anchor: Anchor::Regex(RuleRegex(regex::Regex::new("f.").unwrap()))
+            }),
+            Some("ca\u{0301}fe\u{0301}")
+        );
+        assert_eq!(at(Position::Start), Some(""));
+        assert_eq!(
+            resolve(
+                "---",
+                &Position::Before {
+                    anchor: Anchor::Digit
+                }
+            ),
+            None
+        );
+    }
+}
@@ -0,0 +1,240 @@
+use crate::ocd::error::OcdError;
+use crate::ocd::mrn::diagnostic::did_you_mean;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Rules saved under a name in `presets.toml`, and used in the rules as
+/// `@name`:
+///
+/// ```toml
+/// music-clean = "s,tc,sd"
+/// albums = """
+/// # Artist - Title becomes Title Artist
+/// @music-clean
+/// p "{X} - {N}" "{2} {1}"
+/// """
+/// ```
+///
+/// `@` followed by a path, i.e. anything with a `/` in it, reads the rules
+/// from that file instead. Both are written one or more rules per line, with
+/// comments starting at a `#` outside of a string.
+///
+/// The file is only read when the rules refer to a preset.
+pub struct Presets {
+    path: PathBuf,
+    presets: Option<BTreeMap<String, String>>,
+}
+
+impl Presets {
+    pub fn new(path: &Path) -> Presets {
+        Presets {
+            path: path.to_path_buf(),
+            presets: None,
+        }
+    }
+
+    fn presets(&mut self) -> Result<&BTreeMap<String, String>, OcdError> {
+        if self.presets.is_none() {
+            self.presets = Some(load(&self.path)?);
+        }
+        Ok(self.presets.as_ref().unwrap())
+    }
+
+    /// The rules of the preset or rule file `name`.
+    fn rules(&mut self, name: &str) -> Result<String, OcdError> {
+        if name.contains('/') {
+            let path = PathBuf::from(name);
+            return fs::read_to_string(&path).map_err(|reason| OcdError::filesystem(&path, reason));
+        }
+        let path = self.path.clone();
+        let presets = self.presets()?;
+        match presets.get(name) {
+            Some(rules) => Ok(rules.clone()),
+            None => {
+                let names: Vec<&str> = presets.keys().map(String::as_str).collect();
+                let message = match did_you_mean(name, &names) {
+                    Some(hint) => format!("Error: unknown preset @{}, {}", name, hint),
+                    None => format!("Error: unknown preset @{} in {:?}", name, path),
+                };
+                Err(OcdError::Parse {
+                    message,
+                    diagnostic: None,
+                })
+            }
+        }
+    }
+}
+
+pub fn default_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("ocd")
+        .join("presets.toml")
+}
+
+/// Reads the presets, of which there are none if the file does not exist.
+fn load(path: &Path) -> Result<BTreeMap<String, String>, OcdError> {
+    match fs::read_to_string(path) {
+        Ok(text) => toml::from_str(&text).map_err(|reason| OcdError::Parse {
+            message: format!("Error reading the presets in {:?}: {}", path, reason),
+            diagnostic: None,
+        }),
+        Err(reason) if reason.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(reason) => Err(OcdError::filesystem(path, reason)),
+    }
+}
+
+/// Replaces every `@name` outside of a string in the rules with the rules of
+/// that preset or file, which may themselves include others.
+pub fn expand(rules: &str, presets: &mut Presets) -> Result<String, OcdError> {
+    expand_included(rules, presets, &mut Vec::new())
+}
+
+/// Expands `rules`, which were included through the presets in `stack`.
+fn expand_included(
+    rules: &str,
+    presets: &mut Presets,
+    stack: &mut Vec<String>,
+) -> Result<String, OcdError> {
+    let mut expanded = String::new();
+    let mut chars = rules.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if c == '"' {
+            in_string = !in_string;
+        }
+        if c != '@' || in_string {
+            expanded.push(c);
+            continue;
+        }
+        let mut name = String::new();
+        while let Some(&c) = chars.peek() {
+            if matches!(c, ',' | ' ' | '"' | '{' | '}') {
+                break;
+            }
+            name.push(c);
+            chars.next();
+        }
+        if stack.contains(&name) {
+            let chain: Vec<String> = stack.iter().map(|name| format!("@{}", name)).collect();
+            return Err(OcdError::Parse {
+                message: format!(
+                    "Error: preset @{} includes itself: {} -> @{}",
+                    name,
+                    chain.join(" -> "),
+                    name
+                ),
+                diagnostic: None,
+            });
+        }
+        let included = join_lines(&presets.rules(&name)?);
+        stack.push(name);
+        expanded.push_str(&expand_included(&included, presets, stack)?);
+        stack.pop();
+    }
+    Ok(expanded)
+}
+
+/// Joins the lines of a preset or rule file with commas, dropping comments
+/// and blank lines. Lines that open or close a block are joined with a space.
+fn join_lines(rules: &str) -> String {
+    let lines: Vec<&str> = rules
+        .lines()
+        .map(|line| {
+            let mut in_string = false;
+            let end = line
+                .char_indices()
+                .find(|(_, c)| {
+                    if *c == '"' {
+                        in_string = !in_string;
+                    }
+                    *c == '#' && !in_string
+                })
+                .map_or(line.len(), |(i, _)| i);
+            line[..end].trim().trim_end_matches(',')
+        })
+        .filter(|line| !line.is_empty())
+        .collect();
+    let mut joined = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        if i > 0 {
+            let block = lines[i - 1].ends_with('{') || line.starts_with('}');
+            joined.push(if block { ' ' } else { ',' });
+        }
+        joined.push_str(line);
+    }
+    joined
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    fn presets_file(toml: &str) -> (tempfile::NamedTempFile, Presets) {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(toml.as_bytes()).unwrap();
+        let presets = Presets::new(file.path());
+        (file, presets)
+    }
+
+    #[test]
+    fn expand_test() {
+        let (_file, mut presets) = presets_file(
+            r##"
+clean = "s,tc"
+music = """
+# Artist - Title becomes Title Artist
+@clean,sd  # spaces to dashes
+p "{X} - {N}" "{2} {1}"
+"""
+"##,
+        );
+        assert_eq!(expand("lc", &mut presets).unwrap(), "lc");
+        assert_eq!(
+            expand("if dir {@clean}", &mut presets).unwrap(),
+            "if dir {s,tc}"
+        );
+        assert_eq!(
+            expand("@music,r \"@clean\" \"#\"", &mut presets).unwrap(),
+            "s,tc,sd,p \"{X} - {N}\" \"{2} {1}\",r \"@clean\" \"#\""
+        );
+    }
+
+    #[test]
+    fn rule_file_test() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "lc\n\n# then\nsd,\nif ext=mp3 {{\n  tc\n  s\n}}").unwrap();
+        let mut presets = Presets::new(Path::new("/nonexistent/presets.toml"));
+        let rules = format!("@{},uc", file.path().display());
+        assert_eq!(
+            expand(&rules, &mut presets).unwrap(),
+            "lc,sd,if ext=mp3 { tc,s },uc"
+        );
+    }
+
+    #[test]
+    fn error_test() {
+        let (_file, mut presets) = presets_file("a = \"@b\"\nb = \"lc,@a\"\nclean = \"s\"");
+        match expand("@a", &mut presets) {
+            Err(OcdError::Parse { message, .. }) => {
+                assert_eq!(message, "Error: preset @a includes itself: @a -> @b -> @a")
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+        match expand("@claen", &mut presets) {
+            Err(OcdError::Parse { message, .. }) => assert_eq!(
+                message,
+                "Error: unknown preset @claen, did you mean `clean`?"
+            ),
+            result => panic!("unexpected result {:?}", result),
+        }
+        let (_file, mut presets) = presets_file("a = [");
+        assert!(matches!(
+            expand("@a", &mut presets),
+            Err(OcdError::Parse { .. })
+        ));
+    }
+}
@@ -0,0 +1,123 @@
+use deunicode::deunicode_with_tofu;
+
+/// How the `sa` rule cleans up a name.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Profile {
+    /// Removes only what Windows, FAT and exFAT don't allow in a name.
+    Windows,
+    /// Lower case ASCII letters and digits separated by dashes, for URLs.
+    Slug,
+    /// Transliterates everything to ASCII, e.g. é to e and ß to ss.
+    Ascii,
+}
+
+pub const PROFILES: [(&str, Profile); 3] = [
+    ("windows", Profile::Windows),
+    ("slug", Profile::Slug),
+    ("ascii", Profile::Ascii),
+];
+
+impl Profile {
+    pub fn find(name: &str) -> Option<Profile> {
+        PROFILES
+            .iter()
+            .find(|(profile, _)| *profile == name)
+            .map(|(_, profile)| *profile)
+    }
+}
+
+/// The characters Windows doesn't allow in a name, besides control
+/// characters.
+const FORBIDDEN: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// The names of devices on Windows, which can't be used as file names with any
+/// extension, in any case.
+const RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+pub fn apply(filename: &str, profile: Profile) -> String {
+    match profile {
+        Profile::Windows => {
+            let name: String = filename
+                .chars()
+                .filter(|c| !c.is_control() && !FORBIDDEN.contains(c))
+                .collect();
+            // Windows drops trailing dots and spaces, so names that only
+            // differ in them would collide.
+            unreserve(name.trim_end_matches(['.', ' ']))
+        }
+        Profile::Slug => {
+            let ascii = transliterate(filename).to_lowercase();
+            let words: Vec<&str> = ascii
+                .split(|c: char| !c.is_ascii_alphanumeric())
+                .filter(|word| !word.is_empty())
+                .collect();
+            unreserve(&words.join("-"))
+        }
+        Profile::Ascii => transliterate(filename),
+    }
+}
+
+/// The closest ASCII to `name`, dropping the characters that have none.
+/// Fractions and division slashes come out with a '/', which would move the
+/// file into another directory, so separators become dashes.
+fn transliterate(name: &str) -> String {
+    deunicode_with_tofu(name, "").replace(['/', '\\'], "-")
+}
+
+/// Adds an underscore to a name that is reserved on Windows. Only the part
+/// before the first dot counts, so `CON.tar` is reserved as well, and the
+/// underscore goes right after it.
+fn unreserve(name: &str) -> String {
+    let base = name.split('.').next().unwrap_or_default().trim_end();
+    if RESERVED
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(base))
+    {
+        format!("{}_{}", base, &name[base.len()..])
+    } else {
+        String::from(name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn windows_test() {
+        let windows = |name| apply(name, Profile::Windows);
+        assert_eq!(windows("Café: Live? <1999>"), "Café Live 1999");
+        assert_eq!(windows("a\tb|c*d\"e"), "abcde");
+        assert_eq!(windows("Notes. . "), "Notes");
+        assert_eq!(windows("con"), "con_");
+        assert_eq!(windows("NUL.backup"), "NUL_.backup");
+        assert_eq!(windows("CONSOLE"), "CONSOLE");
+    }
+
+    #[test]
+    fn slug_test() {
+        let slug = |name| apply(name, Profile::Slug);
+        assert_eq!(slug("Café del Mar – Straße 42!"), "cafe-del-mar-strasse-42");
+        assert_eq!(slug("  --Hello__World-- "), "hello-world");
+        assert_eq!(slug("Aux"), "aux_");
+    }
+
+    #[test]
+    fn ascii_test() {
+        let ascii = |name| apply(name, Profile::Ascii);
+        assert_eq!(ascii("Café Straße"), "Cafe Strasse");
+        assert_eq!(ascii("Ångström ÆØ"), "Angstrom AEO");
+        assert_eq!(ascii("plain (text)"), "plain (text)");
+        assert_eq!(ascii("Track ½"), "Track 1-2");
+        assert_eq!(ascii("a⁄b∕c"), "a-b-c");
+    }
+
+    #[test]
+    fn find_test() {
+        assert_eq!(Profile::find("slug"), Some(Profile::Slug));
+        assert_eq!(Profile::find("url"), None);
+    }
+}
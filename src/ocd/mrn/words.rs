@@ -0,0 +1,175 @@
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Splits a name into its words, for the rules that change between case
+/// conventions.
+///
+/// Anything that is not a letter or a digit separates words, so spaces,
+/// underscores, dashes, periods and punctuation are all dropped. Within a run
+/// of letters and digits a new word starts at:
+///
+/// - an uppercase letter following a lowercase letter or a digit
+///   (`camelCase` -> `camel`, `Case`),
+/// - the last uppercase letter of an acronym when it is followed by a
+///   lowercase letter (`XMLHttp` -> `XML`, `Http`).
+///
+/// Digits stay attached to the letters before them (`mp3Player` -> `mp3`,
+/// `Player`). The name is walked by grapheme clusters, so letters followed by
+/// combining marks are kept whole, and apostrophes between letters are kept
+/// inside their word (`don't`).
+pub fn split_words(name: &str) -> Vec<&str> {
+    word_ranges(name)
+        .into_iter()
+        .map(|range| &name[range])
+        .collect()
+}
+
+/// The byte ranges of the words of a name, as split by [`split_words`], for
+/// the rules that need the text between them as well.
+pub fn word_ranges(name: &str) -> Vec<Range<usize>> {
+    let graphemes: Vec<(usize, &str)> = name.grapheme_indices(true).collect();
+    let kinds: Vec<Kind> = graphemes
+        .iter()
+        .enumerate()
+        .map(|(i, (_, grapheme))| match kind(grapheme) {
+            Kind::Apostrophe => {
+                let before = i > 0 && is_letter(graphemes[i - 1].1);
+                let after = i + 1 < graphemes.len() && is_letter(graphemes[i + 1].1);
+                if before && after {
+                    Kind::Apostrophe
+                } else {
+                    Kind::Separator
+                }
+            }
+            kind => kind,
+        })
+        .collect();
+
+    let mut words = Vec::new();
+    let mut start: Option<usize> = None;
+    for i in 0..graphemes.len() {
+        let offset = graphemes[i].0;
+        if kinds[i] == Kind::Separator {
+            if let Some(start) = start.take() {
+                words.push(start..offset);
+            }
+            continue;
+        }
+        match start {
+            None => start = Some(offset),
+            Some(word_start) => {
+                if is_boundary(&kinds, i) {
+                    words.push(word_start..offset);
+                    start = Some(offset);
+                }
+            }
+        }
+    }
+    if let Some(start) = start {
+        words.push(start..name.len());
+    }
+    words
+}
+
+/// What a word is made of, used to tell whether two names have the same shape.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WordKind {
+    Letters,
+    Digits,
+    Mixed,
+}
+
+pub fn word_kind(word: &str) -> WordKind {
+    if word.chars().all(char::is_numeric) {
+        WordKind::Digits
+    } else if word.chars().any(char::is_numeric) {
+        WordKind::Mixed
+    } else {
+        WordKind::Letters
+    }
+}
+
+/// The kinds of the words of a name, in order.
+pub fn shape(words: &[&str]) -> Vec<WordKind> {
+    words.iter().map(|word| word_kind(word)).collect()
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Kind {
+    Upper,
+    Lower,
+    Digit,
+    Apostrophe,
+    Separator,
+}
+
+fn kind(grapheme: &str) -> Kind {
+    // A grapheme is classified by its base character.
+    match grapheme.chars().next() {
+        Some(c) if c.is_uppercase() => Kind::Upper,
+        Some(c) if c.is_numeric() => Kind::Digit,
+        // Letters without case, as in most non-latin scripts, behave as
+        // lowercase ones.
+        Some(c) if c.is_alphabetic() => Kind::Lower,
+        Some('\'') | Some('’') => Kind::Apostrophe,
+        _ => Kind::Separator,
+    }
+}
+
+fn is_letter(grapheme: &str) -> bool {
+    matches!(kind(grapheme), Kind::Upper | Kind::Lower)
+}
+
+/// Whether a new word starts at the grapheme at `i`, given that the grapheme
+/// before it belongs to a word.
+fn is_boundary(kinds: &[Kind], i: usize) -> bool {
+    let previous = kinds[i - 1];
+    let next = kinds.get(i + 1).cloned();
+    match kinds[i] {
+        Kind::Upper => match previous {
+            Kind::Lower | Kind::Digit => true,
+            Kind::Upper => next == Some(Kind::Lower),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{shape, split_words, word_ranges, WordKind};
+
+    macro_rules! t {
+        ($t:ident : $s1:expr => $s2:expr) => {
+            #[test]
+            fn $t() {
+                assert_eq!($s1, $s2)
+            }
+        };
+    }
+
+    t!(empty_test:
+        split_words("") => Vec::<&str>::new());
+    t!(separators_test:
+        split_words(" a_b-c.d, e ") => vec!["a", "b", "c", "d", "e"]);
+    t!(camel_case_test:
+        split_words("camelCaseWords") => vec!["camel", "Case", "Words"]);
+    t!(acronym_test:
+        split_words("XMLHttpRequest") => vec!["XML", "Http", "Request"]);
+    t!(trailing_acronym_test:
+        split_words("parseHTML") => vec!["parse", "HTML"]);
+    t!(digits_test:
+        split_words("mp3Player 01Intro") => vec!["mp3", "Player", "01", "Intro"]);
+    t!(unicode_test:
+        split_words("ÉcoleÀParis straßeΣΧΟΛΕΙΟ") => vec!["École", "À", "Paris", "straße", "ΣΧΟΛΕΙΟ"]);
+    t!(combining_marks_test:
+        split_words("Cafe\u{301}Noir") => vec!["Cafe\u{301}", "Noir"]);
+    t!(surname_test:
+        split_words("Paul McCartney") => vec!["Paul", "Mc", "Cartney"]);
+    t!(apostrophe_test:
+        split_words("don't 'quoted'") => vec!["don't", "quoted"]);
+    t!(ranges_test:
+        word_ranges("é_b  cD") => vec![0..2, 3..4, 6..7, 7..8]);
+    t!(shape_test:
+        shape(&split_words("Track 01 - mp3")) => vec![WordKind::Letters, WordKind::Digits, WordKind::Mixed]);
+}
@@ -1,26 +1,28 @@
 use crate::ocd::config::Verbosity;
-use crate::ocd::mrn::lexer::Token;
+use crate::ocd::journal::{Entry, Operation};
+use crate::ocd::mrn::lexer::Tokens;
 use crate::ocd::mrn::Rule;
+use crate::ocd::settings::Setting;
+use crate::ocd::tss::Destination;
 
 use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
 pub fn mrn_lexer_error(verbosity: Verbosity, msg: &str) {
-    if verbosity.is_silent() {
-        return;
+    if let Verbosity::Debug = verbosity {
+        println!("{}", msg);
     }
-    println!("{}", msg);
 }
 
 pub fn mrn_state(
     config: &crate::ocd::mrn::MassRenameConfig,
-    tokens: &[Token],
+    tokens: &Tokens,
     rules: &[Rule],
     files: &[PathBuf],
 ) {
     if let Verbosity::Debug = config.verbosity {
         println!("{:#?}", &config);
-        println!("Tokens:\n{:#?}", &tokens);
+        println!("Tokens:\n{:#?}", &tokens.lexemes);
         println!("Rules:\n{:#?}", &rules);
         println!("Files:\n{:#?}", &files);
     }
@@ -32,12 +34,11 @@ pub fn mrn_pattern_match(
     match_pattern: &str,
     replace_pattern: &str,
 ) {
-    if verbosity.is_silent() {
-        return;
+    if let Verbosity::Debug = verbosity {
+        println!("filename:        {:?}", filename);
+        println!("match pattern:   {:?}", match_pattern);
+        println!("replace pattern: {:?}", replace_pattern);
     }
-    println!("filename:        {:?}", filename);
-    println!("match pattern:   {:?}", match_pattern);
-    println!("replace pattern: {:?}", replace_pattern);
 }
 
 pub fn mrn_result(verbosity: Verbosity, buffer: &BTreeMap<PathBuf, PathBuf>) {
@@ -50,11 +51,95 @@ pub fn mrn_result(verbosity: Verbosity, buffer: &BTreeMap<PathBuf, PathBuf>) {
     }
 }
 
-pub fn undo_script(verbosity: Verbosity) {
+/// Lists the entries that would get names only differing in their Unicode
+/// normalization, one group per name.
+pub fn mrn_normalization(verbosity: Verbosity, groups: &[Vec<(&PathBuf, &PathBuf)>]) {
     if verbosity.is_silent() {
         return;
     }
-    println!("Creating undo script.");
+    println!("Names that only differ in their Unicode normalization:");
+    for group in groups {
+        println!("---");
+        for (src, dst) in group {
+            println!("    {:?}\n    -> {:?}", src, dst);
+        }
+    }
+}
+
+/// Reports a name that a pattern match left alone because it didn't match.
+pub fn mrn_no_match(verbosity: Verbosity, filename: &str) {
+    if !verbosity.is_high() {
+        return;
+    }
+    println!("No match on {:?}", filename);
+}
+
+/// Shows the names an interactive rule would give to the files. This is part
+/// of the interaction, so it is shown regardless of the verbosity.
+pub fn mrn_preview(names: &[(PathBuf, PathBuf)]) {
+    println!("Preview:");
+    for (before, after) in names {
+        if before == after {
+            println!("    {:?} (unchanged)", file_name(before));
+        } else {
+            println!("    {:?}\n    -> {:?}", file_name(before), file_name(after));
+        }
+    }
+}
+
+pub fn mrn_tokens(tokens: &[&str]) {
+    println!("Tokens:");
+    for (index, token) in tokens.iter().enumerate() {
+        println!("    {}: {:?}", index + 1, token);
+    }
+    println!(
+        "Names split at separators and case changes, so McCartney is two tokens, and\n\
+         only names with the same number and kinds of tokens are renamed. Write the\n\
+         last token as {}+ to take any tokens after it as they are.",
+        tokens.len()
+    );
+}
+
+pub fn mrn_invalid_input(msg: &str) {
+    println!("{}", msg);
+}
+
+fn file_name(path: &Path) -> &std::ffi::OsStr {
+    path.file_name().unwrap_or(path.as_os_str())
+}
+
+pub fn tss_result(verbosity: Verbosity, files: &BTreeMap<PathBuf, Destination>) {
+    if verbosity.is_silent() {
+        return;
+    }
+    println!("Result:");
+    for (src, destination) in files {
+        println!(
+            "---\n    {:?}\n    {:?} (date from {})",
+            src, destination.dir, destination.source
+        )
+    }
+}
+
+pub fn undo_nothing(verbosity: Verbosity) {
+    if verbosity.is_silent() {
+        return;
+    }
+    println!("Nothing to undo.");
+}
+
+pub fn undo_entry(verbosity: Verbosity, entry: &Entry) {
+    if verbosity.is_silent() {
+        return;
+    }
+    match &entry.operation {
+        Operation::Move { src, dst, .. } => {
+            println!("Moving {:?}\n  back to {:?}", dst, src);
+        }
+        Operation::CreateDirectory { path } => {
+            println!("Removing directory {:?}", path);
+        }
+    }
 }
 
 pub fn file_move(verbosity: Verbosity, src: &Path, dst: &Path) {
@@ -63,3 +148,32 @@ pub fn file_move(verbosity: Verbosity, src: &Path, dst: &Path) {
     }
     println!("Moving {:?}\n    to {:?}", src, dst);
 }
+
+pub fn file_rollback(verbosity: Verbosity, src: &Path, dst: &Path) {
+    if verbosity.is_silent() {
+        return;
+    }
+    println!("Rolling back {:?}\n    to {:?}", src, dst);
+}
+
+pub fn rollback_done(verbosity: Verbosity, count: usize) {
+    if verbosity.is_silent() || count == 0 {
+        return;
+    }
+    println!("All changes were rolled back.");
+}
+
+pub fn print_config(settings: &[Setting]) {
+    let name_width = settings.iter().map(|s| s.name.len()).max().unwrap_or(0);
+    let value_width = settings.iter().map(|s| s.value.len()).max().unwrap_or(0);
+    for setting in settings {
+        println!(
+            "{:<name_width$}  {:<value_width$}  ({})",
+            setting.name,
+            setting.value,
+            setting.origin,
+            name_width = name_width,
+            value_width = value_width
+        );
+    }
+}
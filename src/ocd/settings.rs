@@ -0,0 +1,446 @@
+use crate::ocd::config::{verbosity_value, Verbosity};
+use crate::ocd::mrn::diagnostic::did_you_mean;
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The name of the configuration files looked up in the current directory
+/// and its parents.
+pub const DIRECTORY_FILE: &str = ".ocd.toml";
+
+/// The settings that only the global file can set. Any directory above the
+/// current one can hold a `.ocd.toml` file, and a stray one shouldn't be able
+/// to skip the confirmation or point a command at another directory.
+const GLOBAL_ONLY: &[&str] = &["yes", "dir"];
+
+/// Where the value of a setting came from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Origin {
+    Default,
+    File(PathBuf),
+    CommandLine,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::File(path) => write!(f, "{}", path.display()),
+            Origin::CommandLine => write!(f, "command line"),
+        }
+    }
+}
+
+/// The effective value of a setting, as shown by `--print-config`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Setting {
+    pub name: &'static str,
+    pub value: String,
+    pub origin: Origin,
+}
+
+/// The settings of a subcommand. Those given on the command line take
+/// precedence over those in the configuration files, which are read from the
+/// `[mrn]` or `[tss]` table of, in order of precedence:
+///
+/// - the `.ocd.toml` files in the current directory and its parents, the
+///   nearest first,
+/// - `config.toml` in the ocd configuration directory, e.g.
+///   `~/.config/ocd/config.toml`.
+///
+/// Settings are named after the long form of their arguments, e.g.
+/// `dry-run = true` or `date-source = ["exif", "mtime"]`, except for
+/// `verbosity`, which is one of `silent`, `low`, `medium`, `high` or
+/// `debug`. A flag set in a file is turned off on the command line with its
+/// `--no-` form, e.g. `--no-dry-run`.
+pub struct Settings<'a> {
+    matches: &'a clap::ArgMatches<'a>,
+    /// The values read from the configuration files, along with the file each
+    /// was read from.
+    files: BTreeMap<String, (toml::Value, PathBuf)>,
+}
+
+impl<'a> Settings<'a> {
+    pub fn new(
+        matches: &'a clap::ArgMatches<'a>,
+        files: BTreeMap<String, (toml::Value, PathBuf)>,
+    ) -> Settings<'a> {
+        Settings { matches, files }
+    }
+
+    /// Reads the settings of `subcommand` from the configuration files that
+    /// apply in the current directory.
+    pub fn load(
+        matches: &'a clap::ArgMatches<'a>,
+        subcommand: &str,
+        names: &[&str],
+    ) -> Result<Settings<'a>, String> {
+        let dir = std::env::current_dir()
+            .map_err(|reason| format!("Error reading the current directory: {}", reason))?;
+        let files = load_files(&config_paths(&dir), subcommand, names)?;
+        Ok(Settings::new(matches, files))
+    }
+
+    pub fn origin(&self, name: &str) -> Origin {
+        let given = match name {
+            "verbosity" => {
+                self.matches.occurrences_of("verbosity") > 0 || self.matches.is_present("silent")
+            }
+            _ => {
+                self.matches.occurrences_of(name) > 0
+                    || self.matches.occurrences_of(negated(name)) > 0
+            }
+        };
+        match self.files.get(name) {
+            _ if given => Origin::CommandLine,
+            Some((_, path)) => Origin::File(path.clone()),
+            None => Origin::Default,
+        }
+    }
+
+    pub fn flag(&self, name: &str) -> Result<bool, String> {
+        if self.matches.is_present(name) {
+            return Ok(true);
+        }
+        if self.matches.is_present(negated(name)) {
+            return Ok(false);
+        }
+        match self.files.get(name) {
+            None => Ok(false),
+            Some((toml::Value::Boolean(value), _)) => Ok(*value),
+            Some((_, path)) => Err(invalid(name, path, "true or false")),
+        }
+    }
+
+    /// The value of an argument, or its default value in `config.yaml` if it
+    /// has one and is set nowhere.
+    pub fn value(&self, name: &str) -> Result<Option<String>, String> {
+        if self.origin(name) == Origin::CommandLine {
+            return Ok(self.matches.value_of(name).map(String::from));
+        }
+        match self.files.get(name) {
+            None => Ok(self.matches.value_of(name).map(String::from)),
+            Some((toml::Value::String(value), _)) => Ok(Some(value.clone())),
+            Some((toml::Value::Integer(value), _)) => Ok(Some(value.to_string())),
+            Some((_, path)) => Err(invalid(name, path, "a string or a number")),
+        }
+    }
+
+    /// The value of an argument that must be one of `possible`. Those given on
+    /// the command line have already been checked.
+    pub fn choice(&self, name: &str, possible: &[&str]) -> Result<Option<String>, String> {
+        let value = self.value(name)?;
+        match (&value, self.files.get(name)) {
+            (Some(value), Some((_, path))) if !possible.contains(&value.as_str()) => {
+                Err(invalid(name, path, &one_of(possible)))
+            }
+            _ => Ok(value),
+        }
+    }
+
+    pub fn parse<T>(&self, name: &str) -> Result<Option<T>, String>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        match self.value(name)? {
+            None => Ok(None),
+            Some(value) => value.parse().map(Some).map_err(|reason| {
+                format!(
+                    "Error: invalid {} {:?} from the {}: {}",
+                    name,
+                    value,
+                    self.origin(name),
+                    reason
+                )
+            }),
+        }
+    }
+
+    /// The values of an argument that takes several, each one of `possible`.
+    pub fn values(&self, name: &str, possible: &[&str]) -> Result<Option<Vec<String>>, String> {
+        let expected = format!("a list of {}", one_of(possible));
+        self.strings(name, &expected, |value| possible.contains(&value))
+    }
+
+    /// The values of an argument that takes several of any kind.
+    pub fn list(&self, name: &str) -> Result<Option<Vec<String>>, String> {
+        self.strings(name, "a list of strings", |_| true)
+    }
+
+    /// The values of an argument that takes several, given in the files as
+    /// a comma-separated string or an array of strings for which `valid`
+    /// holds.
+    fn strings(
+        &self,
+        name: &str,
+        expected: &str,
+        valid: impl Fn(&str) -> bool,
+    ) -> Result<Option<Vec<String>>, String> {
+        if self.origin(name) == Origin::CommandLine {
+            return Ok(self
+                .matches
+                .values_of(name)
+                .map(|values| values.map(String::from).collect()));
+        }
+        let (value, path) = match self.files.get(name) {
+            None => return Ok(None),
+            Some(entry) => entry,
+        };
+        let values = match value {
+            toml::Value::String(value) => value.split(',').map(String::from).collect(),
+            toml::Value::Array(values) => values
+                .iter()
+                .map(|value| value.as_str().map(String::from))
+                .collect::<Option<Vec<String>>>()
+                .ok_or_else(|| invalid(name, path, expected))?,
+            _ => return Err(invalid(name, path, expected)),
+        };
+        if values.iter().all(|value| valid(value)) {
+            Ok(Some(values))
+        } else {
+            Err(invalid(name, path, expected))
+        }
+    }
+
+    pub fn verbosity(&self) -> Result<Verbosity, String> {
+        if !matches!(self.origin("verbosity"), Origin::File(_)) {
+            return Ok(verbosity_value(self.matches));
+        }
+        let names = ["silent", "low", "medium", "high", "debug"];
+        match self.choice("verbosity", &names)?.as_deref() {
+            Some("silent") => Ok(Verbosity::Silent),
+            Some("medium") => Ok(Verbosity::Medium),
+            Some("high") => Ok(Verbosity::High),
+            Some("debug") => Ok(Verbosity::Debug),
+            _ => Ok(Verbosity::Low),
+        }
+    }
+
+    /// The effective values of the settings, named as in the files.
+    pub fn describe(&self, values: Vec<(&'static str, String)>) -> Vec<Setting> {
+        values
+            .into_iter()
+            .map(|(name, value)| Setting {
+                name,
+                value,
+                origin: self.origin(name),
+            })
+            .collect()
+    }
+}
+
+/// The argument that turns off the flag `name`.
+fn negated(name: &str) -> String {
+    format!("no-{}", name)
+}
+
+fn one_of(possible: &[&str]) -> String {
+    let quoted: Vec<String> = possible
+        .iter()
+        .map(|value| format!("{:?}", value))
+        .collect();
+    format!("one of {}", quoted.join(", "))
+}
+
+fn invalid(name: &str, path: &Path, expected: &str) -> String {
+    format!(
+        "Error in {}: {} should be {}",
+        path.display(),
+        name,
+        expected
+    )
+}
+
+pub fn global_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("ocd").join("config.toml"))
+}
+
+/// The configuration files that apply in `dir`, from the one that takes the
+/// least precedence to the one that takes the most. They need not exist.
+pub fn config_paths(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = global_path().into_iter().collect();
+    let mut nearest: Vec<PathBuf> = dir
+        .ancestors()
+        .map(|ancestor| ancestor.join(DIRECTORY_FILE))
+        .collect();
+    nearest.reverse();
+    paths.extend(nearest);
+    paths
+}
+
+/// Reads the table of `subcommand` from each of the files that exist, the
+/// later ones replacing the values of the earlier ones. Only the settings in
+/// `names` are allowed, and those in `GLOBAL_ONLY` only outside of
+/// `.ocd.toml` files.
+pub fn load_files(
+    paths: &[PathBuf],
+    subcommand: &str,
+    names: &[&str],
+) -> Result<BTreeMap<String, (toml::Value, PathBuf)>, String> {
+    let mut values = BTreeMap::new();
+    for path in paths {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(reason) if reason.kind() == io::ErrorKind::NotFound => continue,
+            Err(reason) => return Err(format!("Error reading {}: {}", path.display(), reason)),
+        };
+        let mut file: toml::value::Table = toml::from_str(&text)
+            .map_err(|reason| format!("Error in {}: {}", path.display(), reason))?;
+        let table = match file.remove(subcommand) {
+            None => continue,
+            Some(toml::Value::Table(table)) => table,
+            Some(_) => {
+                return Err(format!(
+                    "Error in {}: {} should be a table of settings",
+                    path.display(),
+                    subcommand
+                ))
+            }
+        };
+        for (name, value) in table {
+            if !names.contains(&name.as_str()) {
+                let hint = did_you_mean(&name, names)
+                    .map(|hint| format!(", {}", hint))
+                    .unwrap_or_default();
+                return Err(format!(
+                    "Error in {}: unknown setting {:?} for {}{}",
+                    path.display(),
+                    name,
+                    subcommand,
+                    hint
+                ));
+            }
+            let directory_file = path.file_name() == Some(OsStr::new(DIRECTORY_FILE));
+            if directory_file && GLOBAL_ONLY.contains(&name.as_str()) {
+                return Err(format!(
+                    "Error in {}: {} can only be set in the global config.toml",
+                    path.display(),
+                    name
+                ));
+            }
+            values.insert(name, (value, path.clone()));
+        }
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn app() -> clap::App<'static, 'static> {
+        clap::App::new("test")
+            .arg(clap::Arg::with_name("verbosity").short("v").multiple(true))
+            .arg(clap::Arg::with_name("silent").long("silent"))
+            .arg(clap::Arg::with_name("git").long("git"))
+            .arg(clap::Arg::with_name("no-git").long("no-git"))
+            .arg(clap::Arg::with_name("yes").long("yes"))
+            .arg(
+                clap::Arg::with_name("mode")
+                    .long("mode")
+                    .takes_value(true)
+                    .default_value("f"),
+            )
+            .arg(
+                clap::Arg::with_name("num-start")
+                    .long("num-start")
+                    .takes_value(true),
+            )
+    }
+
+    fn files(dir: &Path, texts: &[&str]) -> Vec<PathBuf> {
+        texts
+            .iter()
+            .enumerate()
+            .map(|(i, text)| {
+                let path = dir.join(format!("{}.toml", i));
+                fs::write(&path, text).unwrap();
+                path
+            })
+            .collect()
+    }
+
+    const NAMES: &[&str] = &["verbosity", "git", "yes", "mode", "num-start"];
+
+    #[test]
+    fn precedence_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = files(
+            dir.path(),
+            &[
+                "[mrn]\ngit = true\nmode = \"d\"\nnum-start = 5\nverbosity = \"high\"",
+                "[tss]\ngit = false\n[mrn]\nmode = \"a\"",
+            ],
+        );
+        let values = load_files(&paths, "mrn", NAMES).unwrap();
+        let matches = app().get_matches_from(vec!["test", "--yes", "--num-start", "7"]);
+        let settings = Settings::new(&matches, values);
+        assert_eq!(settings.flag("git"), Ok(true));
+        assert_eq!(settings.origin("git"), Origin::File(paths[0].clone()));
+        assert_eq!(settings.flag("yes"), Ok(true));
+        assert_eq!(settings.origin("yes"), Origin::CommandLine);
+        assert_eq!(
+            settings.choice("mode", &["a", "d", "f"]),
+            Ok(Some(String::from("a")))
+        );
+        assert_eq!(settings.origin("mode"), Origin::File(paths[1].clone()));
+        assert_eq!(settings.parse("num-start"), Ok(Some(7)));
+        assert!(matches!(settings.verbosity(), Ok(Verbosity::High)));
+
+        let matches = app().get_matches_from(vec!["test", "-v"]);
+        let settings = Settings::new(&matches, load_files(&paths, "mrn", NAMES).unwrap());
+        assert_eq!(settings.parse("num-start"), Ok(Some(5)));
+        assert!(matches!(settings.verbosity(), Ok(Verbosity::Medium)));
+
+        let matches = app().get_matches_from(vec!["test", "--no-git"]);
+        let settings = Settings::new(&matches, load_files(&paths, "mrn", NAMES).unwrap());
+        assert_eq!(settings.flag("git"), Ok(false));
+        assert_eq!(settings.origin("git"), Origin::CommandLine);
+
+        let settings = Settings::new(&matches, BTreeMap::new());
+        assert_eq!(
+            settings.choice("mode", &["a", "d", "f"]),
+            Ok(Some(String::from("f")))
+        );
+        assert_eq!(settings.origin("mode"), Origin::Default);
+    }
+
+    #[test]
+    fn error_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = files(dir.path(), &["[mrn]\ngti = true"]);
+        let error = load_files(&paths, "mrn", NAMES).unwrap_err();
+        assert!(error.ends_with("unknown setting \"gti\" for mrn, did you mean `git`?"));
+
+        let paths = files(dir.path(), &["[mrn]\ngit = \"yes\"\nmode = \"x\""]);
+        let matches = app().get_matches_from(vec!["test"]);
+        let settings = Settings::new(&matches, load_files(&paths, "mrn", NAMES).unwrap());
+        assert!(settings
+            .flag("git")
+            .unwrap_err()
+            .ends_with("git should be true or false"));
+        assert!(settings
+            .choice("mode", &["a", "d", "f"])
+            .unwrap_err()
+            .ends_with("mode should be one of \"a\", \"d\", \"f\""));
+    }
+
+    #[test]
+    fn global_only_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(DIRECTORY_FILE);
+        fs::write(&path, "[mrn]\nyes = true").unwrap();
+        let error = load_files(std::slice::from_ref(&path), "mrn", NAMES).unwrap_err();
+        assert!(error.ends_with("yes can only be set in the global config.toml"));
+
+        fs::write(&path, "[mrn]\ngit = true").unwrap();
+        assert!(load_files(&[path], "mrn", NAMES).is_ok());
+        let paths = files(dir.path(), &["[mrn]\nyes = true"]);
+        assert!(load_files(&paths, "mrn", NAMES).is_ok());
+    }
+}
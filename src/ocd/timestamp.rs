@@ -0,0 +1,56 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// The date a picture was taken, read from its `DateTimeOriginal`,
+/// `DateTimeDigitized` or `DateTime` EXIF tags, in that order.
+pub fn exif(path: &Path) -> Option<NaiveDateTime> {
+    let file = fs::File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut io::BufReader::new(&file))
+        .ok()?;
+    // DateTime is when the file was last changed, which for pictures straight
+    // from a camera is also when they were taken.
+    [
+        exif::Tag::DateTimeOriginal,
+        exif::Tag::DateTimeDigitized,
+        exif::Tag::DateTime,
+    ]
+    .iter()
+    .filter_map(|tag| exif.get_field(*tag, exif::In::PRIMARY))
+    .find_map(|field| match field.value {
+        exif::Value::Ascii(ref values) => values
+            .first()
+            .and_then(|value| exif::DateTime::from_ascii(value).ok())
+            .and_then(|datetime| {
+                NaiveDate::from_ymd_opt(
+                    i32::from(datetime.year),
+                    u32::from(datetime.month),
+                    u32::from(datetime.day),
+                )?
+                .and_hms_opt(
+                    u32::from(datetime.hour),
+                    u32::from(datetime.minute),
+                    u32::from(datetime.second),
+                )
+            }),
+        _ => None,
+    })
+}
+
+/// The file's last modification time, in local time.
+pub fn modified(path: &Path) -> Option<NaiveDateTime> {
+    fs::metadata(path).ok()?.modified().ok().map(local)
+}
+
+/// The file's creation time, in local time, on the platforms and filesystems
+/// that record it.
+pub fn created(path: &Path) -> Option<NaiveDateTime> {
+    fs::metadata(path).ok()?.created().ok().map(local)
+}
+
+fn local(time: SystemTime) -> NaiveDateTime {
+    DateTime::<Local>::from(time).naive_local()
+}
@@ -0,0 +1,214 @@
+use crate::ocd::tss::DateSource;
+use chrono::{Datelike, NaiveDate};
+use std::path::{Component, Path, PathBuf};
+
+/// A destination directory template such as `{year}/{month}/{year}-{month}-{day}`.
+///
+/// Fields are written between braces. Numeric fields take an optional width,
+/// `{month:1}` or `{year:04}`, and are padded with zeros up to it; `{month}` and
+/// `{day}` default to two digits. `{{` and `}}` stand for literal braces and
+/// `/` separates directories.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layout {
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Field { field: Field, width: Option<usize> },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Field {
+    Year,
+    Month,
+    Day,
+    Filename,
+    Extension,
+    Source,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Field> {
+        match name {
+            "year" => Some(Field::Year),
+            "month" => Some(Field::Month),
+            "day" => Some(Field::Day),
+            "filename" => Some(Field::Filename),
+            "extension" => Some(Field::Extension),
+            "source" => Some(Field::Source),
+            _ => None,
+        }
+    }
+
+    fn is_numeric(self) -> bool {
+        matches!(self, Field::Year | Field::Month | Field::Day)
+    }
+}
+
+pub const DEFAULT_LAYOUT: &str = "{year}-{month}-{day}";
+
+impl Layout {
+    pub fn parse(template: &str) -> Result<Layout, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest
+                        .find('}')
+                        .ok_or_else(|| format!("Layout error: unclosed field in {:?}", template))?;
+                    parts.push(Part::Text(std::mem::take(&mut text)));
+                    parts.push(parse_field(&rest[..end])?);
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => {
+                    return Err(format!(
+                        "Layout error: unmatched '}}' in {:?}, write '}}}}' for a literal brace",
+                        template
+                    ))
+                }
+                _ => text.push(c),
+            }
+        }
+        parts.push(Part::Text(text));
+        parts.retain(|part| part != &Part::Text(String::new()));
+        if parts.is_empty() {
+            return Err(String::from("Layout error: the layout is empty"));
+        }
+        Ok(Layout { parts })
+    }
+
+    /// Renders the layout for a file, returning a path relative to the
+    /// directory being sorted.
+    pub fn render(
+        &self,
+        date: NaiveDate,
+        source: DateSource,
+        path: &Path,
+    ) -> Result<PathBuf, String> {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => rendered.push_str(text),
+                Part::Field { field, width } => {
+                    let value = match field {
+                        Field::Year => date.year().to_string(),
+                        Field::Month => date.month().to_string(),
+                        Field::Day => date.day().to_string(),
+                        Field::Filename => lossy(path.file_stem()),
+                        Field::Extension => lossy(path.extension()),
+                        Field::Source => source.to_string(),
+                    };
+                    let width = width.unwrap_or(match field {
+                        Field::Month | Field::Day => 2,
+                        _ => 0,
+                    });
+                    rendered.push_str(&format!("{:0>width$}", value, width = width));
+                }
+            }
+        }
+
+        let relative: PathBuf = rendered.split('/').filter(|s| !s.is_empty()).collect();
+        let valid = relative.components().count() > 0
+            && relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)));
+        if valid {
+            Ok(relative)
+        } else {
+            Err(format!(
+                "Layout error: {:?} is not a valid directory for {:?}",
+                rendered, path
+            ))
+        }
+    }
+}
+
+fn parse_field(field: &str) -> Result<Part, String> {
+    let (name, width) = match field.find(':') {
+        None => (field, None),
+        Some(index) => (&field[..index], Some(&field[index + 1..])),
+    };
+    let field = Field::from_name(name)
+        .ok_or_else(|| format!("Layout error: unknown field {{{}}}", name))?;
+    let width =
+        match width {
+            None => None,
+            Some(_) if !field.is_numeric() => {
+                return Err(format!("Layout error: {{{}}} does not take a width", name))
+            }
+            Some(width) => Some(width.parse::<usize>().map_err(|_| {
+                format!("Layout error: invalid width {:?} for {{{}}}", width, name)
+            })?),
+        };
+    Ok(Part::Field { field, width })
+}
+
+fn lossy(part: Option<&std::ffi::OsStr>) -> String {
+    part.map(|part| part.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn render(layout: &str, path: &str) -> Result<PathBuf, String> {
+        Layout::parse(layout)?.render(
+            NaiveDate::from_ymd_opt(2019, 5, 3).unwrap(),
+            DateSource::Exif,
+            Path::new(path),
+        )
+    }
+
+    #[test]
+    fn default_layout_test() {
+        assert_eq!(
+            render(DEFAULT_LAYOUT, "a.jpg").unwrap(),
+            PathBuf::from("2019-05-03")
+        );
+    }
+
+    #[test]
+    fn nested_layout_test() {
+        assert_eq!(
+            render("{year}/{month:02}/{year}-{month}-{day}", "a.jpg").unwrap(),
+            PathBuf::from("2019/05/2019-05-03")
+        );
+        assert_eq!(
+            render("{year}/{year}-{month:1}", "a.jpg").unwrap(),
+            PathBuf::from("2019/2019-5")
+        );
+    }
+
+    #[test]
+    fn file_fields_test() {
+        assert_eq!(
+            render("{source}/{extension}/{{{filename}}}", "dir/DSC_1.JPG").unwrap(),
+            PathBuf::from("exif/JPG/{DSC_1}")
+        );
+    }
+
+    #[test]
+    fn invalid_layout_test() {
+        assert!(Layout::parse("{year").is_err());
+        assert!(Layout::parse("{week}").is_err());
+        assert!(Layout::parse("{filename:2}").is_err());
+        assert!(Layout::parse("{year}}").is_err());
+        assert!(Layout::parse("").is_err());
+        assert!(render("../{year}", "a.jpg").is_err());
+        assert!(render("{extension}", "a").is_err());
+    }
+}
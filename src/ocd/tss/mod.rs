@@ -1,21 +1,58 @@
-use crate::ocd::config::{directory_value, verbosity_value, Verbosity};
+use crate::ocd::config::{directory_value, Verbosity};
+use crate::ocd::error::OcdError;
+use crate::ocd::journal::Journal;
+use crate::ocd::settings::Settings;
+use crate::ocd::tss::layout::Layout;
+use chrono::NaiveDate;
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::collections::BTreeMap;
-use std::error::Error;
+use std::fmt;
 use std::fs;
 use std::io;
-use std::option;
 use std::path::{Path, PathBuf};
 use walkdir::{DirEntry, WalkDir};
 
+pub mod layout;
+
+/// Where the date used to sort a file is read from.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DateSource {
+    /// The `DateTimeOriginal`, `DateTimeDigitized` or `DateTime` EXIF tags.
+    Exif,
+    /// A `YYYY?MM?DD` date in the file name.
+    Filename,
+    /// The file's last modification time.
+    Modified,
+}
+
+impl fmt::Display for DateSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DateSource::Exif => write!(f, "exif"),
+            DateSource::Filename => write!(f, "filename"),
+            DateSource::Modified => write!(f, "mtime"),
+        }
+    }
+}
+
+/// The directory a file will be moved into, and the source of the date that
+/// was used to pick it.
+#[derive(Clone, Debug)]
+pub struct Destination {
+    pub dir: PathBuf,
+    pub source: DateSource,
+}
+
 #[derive(Clone, Debug)]
 pub struct TimeStampSortConfig {
     pub verbosity: Verbosity,
     pub dir: PathBuf,
     pub dryrun: bool,
-    pub undo: bool,
     pub yes: bool,
+    pub date_sources: Vec<DateSource>,
+    pub layout: String,
+    pub journal: PathBuf,
 }
 
 impl TimeStampSortConfig {
@@ -24,123 +61,310 @@ impl TimeStampSortConfig {
             verbosity: Verbosity::Low,
             dir: PathBuf::new(),
             dryrun: true,
-            undo: false,
             yes: false,
+            date_sources: vec![DateSource::Exif, DateSource::Filename],
+            layout: String::from(layout::DEFAULT_LAYOUT),
+            journal: crate::ocd::journal::default_path(),
         }
     }
 
-    pub fn with_args(&self, matches: &clap::ArgMatches) -> TimeStampSortConfig {
-        TimeStampSortConfig {
-            verbosity: verbosity_value(matches),
-            dir: directory_value(matches.value_of("dir").unwrap()),
-            dryrun: matches.is_present("dry-run"),
-            undo: matches.is_present("undo"),
-            yes: matches.is_present("yes"),
-        }
+    pub fn with_args(&self, settings: &Settings) -> Result<TimeStampSortConfig, String> {
+        let date_sources = settings.values("date-source", &["exif", "filename", "mtime"])?;
+        Ok(TimeStampSortConfig {
+            verbosity: settings.verbosity()?,
+            dir: directory_value(&settings.value("dir")?.unwrap()),
+            dryrun: settings.flag("dry-run")?,
+            yes: settings.flag("yes")?,
+            date_sources: date_sources.map_or_else(
+                || self.date_sources.clone(),
+                |values| {
+                    values
+                        .iter()
+                        .map(|value| date_source_value(value))
+                        .collect()
+                },
+            ),
+            layout: settings
+                .value("layout")?
+                .unwrap_or_else(|| self.layout.clone()),
+            journal: self.journal.clone(),
+        })
     }
-}
 
-pub fn run(config: &TimeStampSortConfig) -> Result<(), Box<dyn Error>> {
-    if !config.dryrun && config.undo {
-        crate::ocd::output::undo_script(config.verbosity);
-        // TODO: implement undo
+    /// The values of the settings that can be read from the configuration
+    /// files.
+    pub fn settings(&self) -> Vec<(&'static str, String)> {
+        let date_sources: Vec<String> = self
+            .date_sources
+            .iter()
+            .map(|source| source.to_string())
+            .collect();
+        vec![
+            ("verbosity", self.verbosity.to_string()),
+            ("dir", self.dir.display().to_string()),
+            ("dry-run", self.dryrun.to_string()),
+            ("yes", self.yes.to_string()),
+            ("date-source", date_sources.join(",")),
+            ("layout", self.layout.clone()),
+        ]
     }
+}
 
+pub fn run(config: &TimeStampSortConfig) -> Result<(), OcdError> {
+    let layout = Layout::parse(&config.layout).map_err(|message| OcdError::Parse {
+        message,
+        diagnostic: None,
+    })?;
     let mut files = BTreeMap::new();
     for entry in WalkDir::new(&config.dir) {
         match entry {
             Ok(entry) => {
-                insert_if_timestamped(config, &mut files, entry);
+                insert_if_timestamped(config, &layout, &mut files, entry)?;
+            }
+            Err(reason) => {
+                let path = reason.path().unwrap_or(&config.dir).to_path_buf();
+                return Err(OcdError::Filesystem {
+                    path,
+                    source: reason.into(),
+                });
             }
-            Err(reason) => return Err(Box::new(reason)),
         }
     }
 
+    crate::ocd::output::tss_result(config.verbosity, &files);
+
     if config.yes || crate::ocd::input::user_confirm() {
-        for (src, dst) in files {
-            create_dir_and_move_file(config, src, dst)?;
+        let mut journal = Journal::new(&config.journal, "tss");
+        let mut result = Ok(());
+        for (src, destination) in files {
+            result = create_dir_and_move_file(config, &mut journal, src, destination.dir);
+            if result.is_err() {
+                break;
+            }
         }
+        // Record whatever was moved, even if a later move failed.
+        journal
+            .commit()
+            .map_err(|reason| OcdError::filesystem(&config.journal, reason))?;
+        result?;
     }
 
     Ok(())
 }
 
+pub fn date_source_value(source: &str) -> DateSource {
+    match source {
+        "exif" => DateSource::Exif,
+        "filename" => DateSource::Filename,
+        "mtime" => DateSource::Modified,
+        _ => DateSource::Filename,
+    }
+}
+
 fn insert_if_timestamped(
     config: &TimeStampSortConfig,
-    files: &mut BTreeMap<PathBuf, PathBuf>,
+    layout: &Layout,
+    files: &mut BTreeMap<PathBuf, Destination>,
     entry: DirEntry,
-) {
+) -> Result<(), OcdError> {
     let path = entry.into_path();
     if !path.is_dir() {
-        if let Some(destination) = destination(&config.dir, &path) {
-            files.insert(path, destination);
+        if let Some(destination) = destination(config, layout, &path)? {
+            // Files that are already in place are left alone.
+            if path.parent() != Some(destination.dir.as_path()) {
+                files.insert(path, destination);
+            }
         }
     }
+    Ok(())
 }
 
 fn create_dir_and_move_file(
     config: &TimeStampSortConfig,
+    journal: &mut Journal,
     file: PathBuf,
     destination: PathBuf,
-) -> Result<(), Box<dyn Error>> {
-    create_directory(config, &destination)?;
-    move_file(config, &file, &destination)?;
-    Ok(())
+) -> Result<(), OcdError> {
+    create_directory(config, journal, &destination)?;
+    move_file(config, journal, &file, &destination)
 }
 
-fn destination(base_dir: &Path, file_name: &Path) -> option::Option<PathBuf> {
-    // let file = std::fs::File::open(file_name).unwrap();
-    // let reader = exif::Reader::new(&mut std::io::BufReader::new(&file)).unwrap();
-    // for f in reader.fields() {
-    //     f.tag, f.thumbnail, f.value.display_as(f.tag));
-    // }
-    file_name
-        .to_str()
-        .and_then(date)
-        .map(|(year, month, day)| base_dir.join(format!("{}-{}-{}", year, month, day)))
+fn destination(
+    config: &TimeStampSortConfig,
+    layout: &Layout,
+    path: &Path,
+) -> Result<Option<Destination>, OcdError> {
+    match file_date(&config.date_sources, path) {
+        None => Ok(None),
+        Some((date, source)) => {
+            let dir = layout
+                .render(date, source, path)
+                .map_err(|message| OcdError::Rule {
+                    rule: config.layout.clone(),
+                    path: path.to_path_buf(),
+                    message,
+                })?;
+            let dir = config.dir.join(dir);
+            Ok(Some(Destination { dir, source }))
+        }
+    }
+}
+
+/// Tries each date source in order of priority and returns the first date
+/// found, along with the source it came from.
+fn file_date(sources: &[DateSource], path: &Path) -> Option<(NaiveDate, DateSource)> {
+    sources.iter().find_map(|source| {
+        let date = match source {
+            DateSource::Exif => crate::ocd::timestamp::exif(path).map(|datetime| datetime.date()),
+            DateSource::Filename => path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(date),
+            DateSource::Modified => {
+                crate::ocd::timestamp::modified(path).map(|datetime| datetime.date())
+            }
+        };
+        date.map(|date| (date, *source))
+    })
 }
 
-fn date(filename: &str) -> Option<(&str, &str, &str)> {
+fn date(filename: &str) -> Option<NaiveDate> {
     lazy_static! {
         // YYYY?MM?DD or YYYYMMDD,
-        // where YYYY in [1000-2999], MM in [01-12], DD in [01-31]
+        // where YYYY in [1000-2999], MM in [01-12], DD in [01-31]
         static ref RE: Regex = Regex::new(r"\D*(1\d\d\d|20\d\d).?(0[1-9]|1[012]).?(0[1-9]|[12]\d|30|31)\D*").unwrap();
     }
-    RE.captures(filename).map(|captures| {
-        let year = captures.get(1).unwrap().as_str();
-        let month = captures.get(2).unwrap().as_str();
-        let day = captures.get(3).unwrap().as_str();
-        (year, month, day)
+    RE.captures(filename).and_then(|captures| {
+        let year = captures.get(1).unwrap().as_str().parse().ok()?;
+        let month = captures.get(2).unwrap().as_str().parse().ok()?;
+        let day = captures.get(3).unwrap().as_str().parse().ok()?;
+        NaiveDate::from_ymd_opt(year, month, day)
     })
 }
 
-fn create_directory(config: &TimeStampSortConfig, directory: &Path) -> io::Result<()> {
+/// Creates the directory and any missing parents, recording each one in the
+/// journal so that undoing the run removes them again.
+fn create_directory(
+    config: &TimeStampSortConfig,
+    journal: &mut Journal,
+    directory: &Path,
+) -> Result<(), OcdError> {
     if !config.dryrun {
-        let mut full_path = PathBuf::new();
-        full_path.push(directory);
-        match fs::create_dir(&full_path) {
-            Ok(_) => return Ok(()),
-            Err(reason) => match reason.kind() {
-                io::ErrorKind::AlreadyExists => return Ok(()),
-                _ => return Err(reason),
-            },
+        let missing: Vec<&Path> = directory
+            .ancestors()
+            .take_while(|ancestor| !ancestor.as_os_str().is_empty() && !ancestor.exists())
+            .collect();
+        for ancestor in missing.iter().rev() {
+            match fs::create_dir(ancestor) {
+                Ok(_) => journal
+                    .record_create_directory(ancestor)
+                    .map_err(|reason| OcdError::filesystem(ancestor, reason))?,
+                Err(reason) => match reason.kind() {
+                    io::ErrorKind::AlreadyExists => {}
+                    _ => return Err(OcdError::filesystem(ancestor, reason)),
+                },
+            }
         }
     }
     Ok(())
 }
 
-fn move_file(config: &TimeStampSortConfig, from: &Path, dest: &Path) -> io::Result<()> {
+fn move_file(
+    config: &TimeStampSortConfig,
+    journal: &mut Journal,
+    from: &Path,
+    dest: &Path,
+) -> Result<(), OcdError> {
     let mut to = PathBuf::new();
     to.push(dest);
     to.push(from.file_name().unwrap());
 
+    // fs::rename would silently replace a file that is already there, and
+    // the journal couldn't bring it back.
+    if fs::symlink_metadata(&to).is_ok() {
+        return Err(OcdError::Collision {
+            src: from.to_path_buf(),
+            dst: to,
+            other: None,
+        });
+    }
+
     crate::ocd::output::file_move(config.verbosity, from, &to);
 
     if !config.dryrun {
-        if config.undo {
-            // TODO implement undo script
-        }
-        fs::rename(from, to)?
+        fs::rename(from, &to).map_err(|reason| OcdError::filesystem(from, reason))?;
+        journal
+            .record_move(from, &to, false)
+            .map_err(|reason| OcdError::filesystem(&to, reason))?;
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Local;
+
+    #[test]
+    fn filename_date_test() {
+        assert_eq!(
+            date("IMG_20190521_123456.jpg"),
+            NaiveDate::from_ymd_opt(2019, 5, 21)
+        );
+        assert_eq!(
+            date("Scan 1998-12-03.pdf"),
+            NaiveDate::from_ymd_opt(1998, 12, 3)
+        );
+        assert_eq!(date("DSC_1234.JPG"), None);
+        assert_eq!(date("2019-02-30.txt"), None);
+    }
+
+    #[test]
+    fn date_source_priority_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("2001-02-03 notes.txt");
+        fs::write(&path, "").unwrap();
+        let today = Local::now().naive_local().date();
+
+        assert_eq!(
+            file_date(&[DateSource::Exif, DateSource::Filename], &path),
+            Some((
+                NaiveDate::from_ymd_opt(2001, 2, 3).unwrap(),
+                DateSource::Filename
+            ))
+        );
+        assert_eq!(
+            file_date(&[DateSource::Modified, DateSource::Filename], &path),
+            Some((today, DateSource::Modified))
+        );
+        assert_eq!(file_date(&[DateSource::Exif], &path), None);
+    }
+
+    #[test]
+    fn existing_destination_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("2024-01-05.txt");
+        let existing = dir.path().join("2024").join("2024-01-05.txt");
+        fs::create_dir(dir.path().join("2024")).unwrap();
+        fs::write(&existing, "existing").unwrap();
+        fs::write(&src, "new").unwrap();
+        let mut config = TimeStampSortConfig::new();
+        config.verbosity = Verbosity::Silent;
+        config.dir = dir.path().to_path_buf();
+        config.dryrun = false;
+        config.yes = true;
+        config.date_sources = vec![DateSource::Filename];
+        config.layout = String::from("{year}");
+        config.journal = dir.path().join("journal.jsonl");
+
+        match run(&config) {
+            Err(OcdError::Collision { src: from, dst, .. }) => {
+                assert_eq!(from, src);
+                assert_eq!(dst, existing);
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "existing");
+        assert_eq!(fs::read_to_string(&src).unwrap(), "new");
+    }
+}
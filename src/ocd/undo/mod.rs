@@ -0,0 +1,327 @@
+use crate::ocd::config::{verbosity_value, Verbosity};
+use crate::ocd::error::OcdError;
+use crate::ocd::journal::{Entry, FileMetadata, Operation};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(Clone, Debug)]
+pub struct UndoConfig {
+    pub verbosity: Verbosity,
+    pub dryrun: bool,
+    pub yes: bool,
+    pub count: Option<usize>,
+    pub journal: PathBuf,
+}
+
+impl UndoConfig {
+    pub fn new() -> UndoConfig {
+        UndoConfig {
+            verbosity: Verbosity::Low,
+            dryrun: true,
+            yes: false,
+            count: None,
+            journal: crate::ocd::journal::default_path(),
+        }
+    }
+
+    pub fn with_args(&self, matches: &clap::ArgMatches) -> UndoConfig {
+        fn count_value(matches: &clap::ArgMatches) -> Option<usize> {
+            if matches.is_present("count") {
+                Some(value_t!(matches, "count", usize).unwrap_or_else(|error| error.exit()))
+            } else {
+                None
+            }
+        }
+
+        UndoConfig {
+            verbosity: verbosity_value(matches),
+            dryrun: matches.is_present("dry-run"),
+            yes: matches.is_present("yes"),
+            count: count_value(matches),
+            journal: self.journal.clone(),
+        }
+    }
+}
+
+pub fn run(config: &UndoConfig) -> Result<(), OcdError> {
+    let journal_error = |reason: io::Error| match reason.kind() {
+        io::ErrorKind::InvalidData => OcdError::Journal {
+            path: config.journal.clone(),
+            message: format!("the journal is corrupt: {}", reason),
+        },
+        _ => OcdError::filesystem(&config.journal, reason),
+    };
+    let mut entries = crate::ocd::journal::load(&config.journal).map_err(journal_error)?;
+    let first = first_undone(&entries, config.count);
+    if first == entries.len() {
+        crate::ocd::output::undo_nothing(config.verbosity);
+        return Ok(());
+    }
+
+    check(&entries[first..])?;
+
+    if config.dryrun {
+        for entry in entries[first..].iter().rev() {
+            crate::ocd::output::undo_entry(config.verbosity, entry);
+        }
+        return Ok(());
+    }
+
+    if config.yes || crate::ocd::input::user_confirm() {
+        while entries.len() > first {
+            let entry = entries.last().unwrap();
+            crate::ocd::output::undo_entry(config.verbosity, entry);
+            if let Err(error) = revert(entry) {
+                crate::ocd::journal::save(&config.journal, &entries).map_err(journal_error)?;
+                return Err(error);
+            }
+            entries.pop();
+        }
+        crate::ocd::journal::save(&config.journal, &entries).map_err(journal_error)?;
+    }
+    Ok(())
+}
+
+/// Index of the first entry to undo: the last `count` entries if a count was
+/// given, otherwise every entry of the most recent run.
+fn first_undone(entries: &[Entry], count: Option<usize>) -> usize {
+    match (count, entries.last()) {
+        (_, None) => 0,
+        (Some(count), _) => entries.len().saturating_sub(count),
+        (None, Some(last)) => entries
+            .iter()
+            .rposition(|entry| entry.run != last.run)
+            .map_or(0, |position| position + 1),
+    }
+}
+
+/// Verifies, before touching the filesystem, that every entry can be undone:
+/// each moved file must still be where ocd left it, unchanged, and its
+/// original location must be free by the time it is restored.
+fn check(entries: &[Entry]) -> Result<(), OcdError> {
+    // The entries undone so far, most recent last, whose effects on the
+    // filesystem are simulated rather than read from disk.
+    let mut undone: Vec<&Operation> = Vec::new();
+
+    for entry in entries.iter().rev() {
+        match &entry.operation {
+            Operation::Move {
+                src, dst, metadata, ..
+            } => {
+                let current = match on_disk(&undone, dst) {
+                    Some(current) if fs::symlink_metadata(&current).is_ok() => current,
+                    _ => return Err(journal_entry_error(dst, "it no longer exists")),
+                };
+                if FileMetadata::read(&current).ok().as_ref() != Some(metadata) {
+                    return Err(journal_entry_error(
+                        dst,
+                        "it has changed since it was moved",
+                    ));
+                }
+                let taken = on_disk(&undone, src)
+                    .is_some_and(|current| fs::symlink_metadata(current).is_ok());
+                if taken {
+                    return Err(OcdError::Collision {
+                        src: dst.clone(),
+                        dst: src.clone(),
+                        other: None,
+                    });
+                }
+            }
+            Operation::CreateDirectory { path } => {
+                let exists = on_disk(&undone, path)
+                    .is_some_and(|current| fs::symlink_metadata(current).is_ok());
+                if !exists {
+                    return Err(journal_entry_error(path, "it no longer exists"));
+                }
+            }
+        }
+        undone.push(&entry.operation);
+    }
+    Ok(())
+}
+
+/// Where the entry that will be at `path` once the `undone` operations are
+/// reverted is on disk now, or `None` if nothing will be there. Reverting the
+/// move of a directory brings back everything inside it, so paths are
+/// rewritten by prefix.
+fn on_disk(undone: &[&Operation], path: &Path) -> Option<PathBuf> {
+    let mut path = path.to_path_buf();
+    for operation in undone.iter().rev() {
+        match operation {
+            Operation::Move { src, dst, .. } => {
+                if let Ok(rest) = path.strip_prefix(src) {
+                    path = dst.join(rest);
+                } else if path.starts_with(dst) {
+                    return None;
+                }
+            }
+            Operation::CreateDirectory { path: directory } => {
+                if path.starts_with(directory) {
+                    return None;
+                }
+            }
+        }
+    }
+    Some(path)
+}
+
+fn journal_entry_error(path: &Path, message: &str) -> OcdError {
+    OcdError::Journal {
+        path: path.to_path_buf(),
+        message: String::from(message),
+    }
+}
+
+fn revert(entry: &Entry) -> Result<(), OcdError> {
+    match &entry.operation {
+        Operation::Move { src, dst, git, .. } => {
+            if *git {
+                let git_error = |message| OcdError::Git {
+                    path: dst.clone(),
+                    message,
+                };
+                let output = Command::new("git")
+                    .arg("mv")
+                    .arg(dst)
+                    .arg(src)
+                    .output()
+                    .map_err(|reason| git_error(format!("unable to invoke git: {}", reason)))?;
+                if !output.status.success() {
+                    return Err(git_error(
+                        String::from_utf8_lossy(&output.stderr).trim().to_string(),
+                    ));
+                }
+                Ok(())
+            } else {
+                fs::rename(dst, src).map_err(|reason| OcdError::filesystem(dst, reason))
+            }
+        }
+        Operation::CreateDirectory { path } => {
+            fs::remove_dir(path).map_err(|reason| OcdError::filesystem(path, reason))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ocd::journal::Journal;
+
+    fn config(journal: &Path) -> UndoConfig {
+        UndoConfig {
+            verbosity: Verbosity::Silent,
+            dryrun: false,
+            yes: true,
+            count: None,
+            journal: journal.to_path_buf(),
+        }
+    }
+
+    #[test]
+    fn undo_last_run_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal_path = dir.path().join("journal.jsonl");
+        let (a, b, c) = (
+            dir.path().join("a"),
+            dir.path().join("b"),
+            dir.path().join("c"),
+        );
+        fs::write(&a, "a").unwrap();
+
+        fs::rename(&a, &b).unwrap();
+        let mut journal = Journal::new(&journal_path, "mrn");
+        journal.record_move(&a, &b, false).unwrap();
+        journal.commit().unwrap();
+
+        let sub = dir.path().join("sub");
+        fs::create_dir(&sub).unwrap();
+        fs::rename(&b, &c).unwrap();
+        let mut journal = Journal::new(&journal_path, "tss");
+        journal.record_create_directory(&sub).unwrap();
+        journal.record_move(&b, &c, false).unwrap();
+        journal.commit().unwrap();
+
+        run(&config(&journal_path)).unwrap();
+        assert!(b.exists() && !c.exists() && !sub.exists());
+        assert_eq!(crate::ocd::journal::load(&journal_path).unwrap().len(), 1);
+
+        run(&config(&journal_path)).unwrap();
+        assert!(a.exists() && !b.exists());
+        assert!(crate::ocd::journal::load(&journal_path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn undo_count_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal_path = dir.path().join("journal.jsonl");
+        let (a, b, c) = (
+            dir.path().join("a"),
+            dir.path().join("b"),
+            dir.path().join("c"),
+        );
+        fs::write(&a, "a").unwrap();
+        fs::write(&b, "b").unwrap();
+
+        let mut journal = Journal::new(&journal_path, "mrn");
+        fs::rename(&b, &c).unwrap();
+        journal.record_move(&b, &c, false).unwrap();
+        fs::rename(&a, &b).unwrap();
+        journal.record_move(&a, &b, false).unwrap();
+        journal.commit().unwrap();
+
+        let mut config = config(&journal_path);
+        config.count = Some(1);
+        run(&config).unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "a");
+        assert_eq!(fs::read_to_string(&c).unwrap(), "b");
+        assert_eq!(crate::ocd::journal::load(&journal_path).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn undo_refuses_changed_file_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal_path = dir.path().join("journal.jsonl");
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+        fs::write(&a, "a").unwrap();
+
+        fs::rename(&a, &b).unwrap();
+        let mut journal = Journal::new(&journal_path, "mrn");
+        journal.record_move(&a, &b, false).unwrap();
+        journal.commit().unwrap();
+        fs::write(&b, "changed").unwrap();
+
+        match run(&config(&journal_path)) {
+            Err(OcdError::Journal { path, .. }) => assert_eq!(path, b),
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert!(b.exists() && !a.exists());
+        assert_eq!(crate::ocd::journal::load(&journal_path).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn undo_directory_and_child_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal_path = dir.path().join("journal.jsonl");
+        let (dd, ff) = (dir.path().join("dd"), dir.path().join("dd").join("ff"));
+        fs::create_dir(&dd).unwrap();
+        fs::write(&ff, "ff").unwrap();
+
+        // As a recursive mrn renames them: the child first, then its parent.
+        let mut journal = Journal::new(&journal_path, "mrn");
+        let child = dd.join("FF");
+        fs::rename(&ff, &child).unwrap();
+        journal.record_move(&ff, &child, false).unwrap();
+        let parent = dir.path().join("DD");
+        fs::rename(&dd, &parent).unwrap();
+        journal.record_move(&dd, &parent, false).unwrap();
+        journal.commit().unwrap();
+
+        run(&config(&journal_path)).unwrap();
+        assert_eq!(fs::read_to_string(&ff).unwrap(), "ff");
+        assert!(!parent.exists());
+        assert!(crate::ocd::journal::load(&journal_path).unwrap().is_empty());
+    }
+}